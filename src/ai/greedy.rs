@@ -0,0 +1,40 @@
+//! Heads straight for the nearest apple, without looking at what is in the way
+
+use super::{wrapped_offset, GameView, SnakeBrain};
+use crate::Direction;
+
+pub(super) struct Greedy;
+
+impl SnakeBrain for Greedy {
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction> {
+        let snake = view.snake(id)?;
+        let &head = snake.body.first()?;
+        let offset = view
+            .apples
+            .iter()
+            .map(|&apple| wrapped_offset(head, apple, view.half_len))
+            .min_by_key(|offset| offset.x.abs() + offset.y.abs())?;
+
+        let direction_x = match offset.x {
+            x if x < 0 => Some(Direction::Left),
+            x if x > 0 => Some(Direction::Right),
+            _ => None,
+        };
+        let direction_y = match offset.y {
+            y if y < 0 => Some(Direction::Down),
+            y if y > 0 => Some(Direction::Up),
+            _ => None,
+        };
+
+        // The longest way first, the other one when that would reverse the snake
+        let (first, second) = if offset.x.abs() >= offset.y.abs() {
+            (direction_x, direction_y)
+        } else {
+            (direction_y, direction_x)
+        };
+        [first, second]
+            .into_iter()
+            .flatten()
+            .find(|&direction| direction != !snake.direction)
+    }
+}
@@ -0,0 +1,242 @@
+//! Scores each move with a weighted sum of what it leads to, the weights can be tuned with `snake-evolve`
+
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{neighbor, wrapped_offset, DifficultySettings, GameView, SnakeBrain};
+use crate::Direction;
+
+/// How much each feature of a move counts, positive weights make the snake look for more of it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub(crate) struct Weights {
+    /// Cells to the nearest apple from where the head ends up
+    pub(crate) apple_distance: f32,
+    /// Cells the snake can still get to from there, up to the lookahead
+    pub(crate) free_space: f32,
+    /// Cells to the nearest head of another snake
+    pub(crate) head_distance: f32,
+    /// Moving into a body
+    pub(crate) collision: f32,
+    /// Moving next to another head, which could move into the same cell
+    pub(crate) head_on: f32,
+    /// Not turning
+    pub(crate) straight: f32,
+}
+
+impl Weights {
+    pub(crate) const LEN: usize = 6;
+
+    /// In the order of the fields, for tuning them all alike
+    pub(crate) fn to_array(self) -> [f32; Self::LEN] {
+        [
+            self.apple_distance,
+            self.free_space,
+            self.head_distance,
+            self.collision,
+            self.head_on,
+            self.straight,
+        ]
+    }
+
+    pub(crate) fn from_array(weights: [f32; Self::LEN]) -> Self {
+        let [apple_distance, free_space, head_distance, collision, head_on, straight] = weights;
+        Self {
+            apple_distance,
+            free_space,
+            head_distance,
+            collision,
+            head_on,
+            straight,
+        }
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            apple_distance: -1.0,
+            free_space: 0.5,
+            head_distance: 0.2,
+            collision: -20.0,
+            head_on: -5.0,
+            straight: 0.1,
+        }
+    }
+}
+
+pub(super) struct Heuristic {
+    weights: Weights,
+    lookahead: usize,
+}
+
+impl Heuristic {
+    pub(super) fn new(settings: &DifficultySettings) -> Self {
+        Self {
+            weights: settings.weights,
+            lookahead: settings.lookahead,
+        }
+    }
+
+    fn score(&self, view: &GameView, id: u8, direction: Direction) -> Option<f32> {
+        let snake = view.snake(id)?;
+        let &head = snake.body.first()?;
+        let next = neighbor(head, direction, view.half_len);
+        let others = view.snakes.iter().filter(|other| other.id != id);
+
+        // Tails move out of the way this tick
+        let occupied = view
+            .snakes
+            .iter()
+            .flat_map(|snake| snake.body.iter().take(snake.body.len().saturating_sub(1)))
+            .copied()
+            .collect::<HashSet<_>>();
+        let collision = snake.inmortal_ticks == 0 && occupied.contains(&next);
+        let head_on = snake.inmortal_ticks == 0
+            && others
+                .clone()
+                .filter_map(|other| other.body.first())
+                .any(|&other| {
+                    let offset = wrapped_offset(next, other, view.half_len);
+                    offset.x.abs() + offset.y.abs() <= 1
+                });
+        let distance = |to: IVec2| {
+            let offset = wrapped_offset(next, to, view.half_len);
+            (offset.x.abs() + offset.y.abs()) as f32
+        };
+        let apple_distance = view
+            .apples
+            .iter()
+            .map(|&apple| distance(apple))
+            .min_by(f32::total_cmp)
+            .unwrap_or_default();
+        let head_distance = others
+            .filter_map(|other| other.body.first())
+            .map(|&other| distance(other))
+            .min_by(f32::total_cmp)
+            .unwrap_or_default();
+        let free_space = self.free_space(next, &occupied, view.half_len) as f32;
+
+        let weights = self.weights;
+        Some(
+            weights.apple_distance * apple_distance
+                + weights.free_space * free_space
+                + weights.head_distance * head_distance
+                + weights.collision * f32::from(u8::from(collision))
+                + weights.head_on * f32::from(u8::from(head_on))
+                + weights.straight * f32::from(u8::from(direction == snake.direction)),
+        )
+    }
+
+    /// Cells reachable from `start` without going through `occupied`, counting up to the lookahead
+    fn free_space(&self, start: IVec2, occupied: &HashSet<IVec2>, half_len: i32) -> usize {
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            if seen.len() >= self.lookahead {
+                break;
+            }
+            for direction in Direction::ALL {
+                let next = neighbor(cell, direction, half_len);
+                if !occupied.contains(&next) && seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen.len().min(self.lookahead)
+    }
+}
+
+impl SnakeBrain for Heuristic {
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction> {
+        let snake = view.snake(id)?;
+        Direction::ALL
+            .into_iter()
+            .filter(|&direction| direction != !snake.direction)
+            .filter_map(|direction| Some((direction, self.score(view, id, direction)?)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(direction, _)| direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::SnakeView;
+
+    const HALF_LEN: i32 = 7;
+
+    fn heuristic(lookahead: usize) -> Heuristic {
+        Heuristic {
+            weights: Weights::default(),
+            lookahead,
+        }
+    }
+
+    fn view(snakes: Vec<SnakeView>, apples: Vec<IVec2>) -> GameView {
+        GameView {
+            half_len: HALF_LEN,
+            length_to_win: 10,
+            inmortal_ticks: 10,
+            proportion_lost_per_hit: 0.3,
+            snakes,
+            apples,
+            headless: true,
+        }
+    }
+
+    fn snake(id: u8, direction: Direction, body: &[(i32, i32)]) -> SnakeView {
+        SnakeView {
+            id,
+            direction,
+            body: body.iter().map(|&(x, y)| IVec2::new(x, y)).collect(),
+            inmortal_ticks: 0,
+        }
+    }
+
+    #[test]
+    fn free_space_counts_up_to_the_lookahead() {
+        let empty = HashSet::new();
+        assert_eq!(heuristic(5).free_space(IVec2::ZERO, &empty, HALF_LEN), 5);
+        let side = (2 * HALF_LEN + 1) as usize;
+        assert_eq!(
+            heuristic(usize::MAX).free_space(IVec2::ZERO, &empty, HALF_LEN),
+            side * side
+        );
+
+        let walls = Direction::ALL
+            .into_iter()
+            .map(|direction| neighbor(IVec2::ZERO, direction, HALF_LEN))
+            .collect();
+        assert_eq!(
+            heuristic(usize::MAX).free_space(IVec2::ZERO, &walls, HALF_LEN),
+            1
+        );
+    }
+
+    #[test]
+    fn moving_into_a_body_scores_lower() {
+        let view = view(
+            vec![
+                snake(1, Direction::Right, &[(0, 0), (-1, 0)]),
+                snake(2, Direction::Up, &[(1, 2), (1, 1), (1, 0), (1, -1)]),
+            ],
+            vec![],
+        );
+        let heuristic = heuristic(14);
+        let into_body = heuristic.score(&view, 1, Direction::Right).unwrap();
+        let free = heuristic.score(&view, 1, Direction::Down).unwrap();
+        assert!(into_body < free);
+    }
+
+    #[test]
+    fn turns_towards_the_nearest_apple() {
+        let view = view(
+            vec![snake(1, Direction::Right, &[(0, 0), (-1, 0)])],
+            vec![IVec2::new(0, -5)],
+        );
+        assert_eq!(heuristic(14).think(&view, 1), Some(Direction::Down));
+    }
+}
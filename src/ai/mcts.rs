@@ -0,0 +1,286 @@
+//! Monte Carlo tree search over the moves of every snake at once
+//! Each snake picks its own move at every node of the tree, decoupled from the others,
+//! and a move is worth what the heuristic in [`evaluate`] says after a short random playout
+//! The search runs on the async compute pool while the game goes on, so the move it finds is for the next tick
+//! Without a window it has a number of iterations instead of a time budget, so matches play out the same every time
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{neighbor, simulation::Simulation, wrapped_offset, GameView, SnakeBrain};
+use crate::Direction;
+
+/// Has to leave time for the rest of the tick, which is 100ms long
+const TIME_BUDGET: Duration = Duration::from_millis(60);
+/// The whole budget of a headless search, and a limit with a window
+const MAX_ITERATIONS: u32 = 5_000;
+/// Ticks into the future of the deepest node
+const MAX_DEPTH: usize = 12;
+/// Ticks of random moves after a new node before evaluating it
+const PLAYOUT_TICKS: usize = 6;
+const EXPLORATION: f32 = 1.4;
+
+const LENGTH_WEIGHT: f32 = 0.6;
+const AREA_WEIGHT: f32 = 0.25;
+const APPLE_WEIGHT: f32 = 0.15;
+
+pub(super) struct Mcts {
+    rng: ChaCha8Rng,
+    /// Started on the previous tick, looking for the move of this one
+    search: Option<Task<Option<Direction>>>,
+}
+
+impl Mcts {
+    pub(super) fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            search: None,
+        }
+    }
+}
+
+impl SnakeBrain for Mcts {
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction> {
+        let planned = self.search.take().and_then(|search| {
+            // With a window the tick can't wait, a search that isn't done yet is too late
+            if search.is_finished() || view.headless {
+                block_on(search)
+            } else {
+                None
+            }
+        });
+
+        let snake = view.snake(id)?;
+        let simulation = Simulation::new(view);
+        let this_tick = planned.unwrap_or(snake.direction);
+        let seed = self.rng.gen();
+        let time_budget = (!view.headless).then_some(TIME_BUDGET);
+        self.search = Some(
+            AsyncComputeTaskPool::get()
+                .spawn(async move { search(simulation, id, this_tick, seed, time_budget) }),
+        );
+        planned
+    }
+}
+
+/// A joint state of the game, reached through the moves of its parents
+struct Node {
+    visits: u32,
+    /// Visits and summed value of each move, by snake and then by index in [`Simulation::moves`]
+    moves: Vec<[(u32, f32); 3]>,
+    /// By the move index of every snake
+    children: HashMap<Vec<usize>, Node>,
+}
+
+impl Node {
+    fn new(snakes: usize) -> Self {
+        Self {
+            visits: 0,
+            moves: vec![[(0, 0.0); 3]; snakes],
+            children: HashMap::new(),
+        }
+    }
+}
+
+/// The best move for the snake with `id` on the tick after `this_tick` is taken
+/// Searches for [`MAX_ITERATIONS`], or until `time_budget` runs out if there is one
+fn search(
+    simulation: Simulation,
+    id: u8,
+    this_tick: Direction,
+    seed: u64,
+    time_budget: Option<Duration>,
+) -> Option<Direction> {
+    let index = simulation.snakes.iter().position(|snake| snake.id == id)?;
+    let forced = simulation
+        .moves(index)
+        .iter()
+        .position(|&direction| direction == this_tick)
+        .unwrap_or(0);
+
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut root = Node::new(simulation.snakes.len());
+    let started = Instant::now();
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS
+        && time_budget.is_none_or(|time_budget| started.elapsed() < time_budget)
+    {
+        iterate(
+            &mut root,
+            &mut simulation.clone(),
+            0,
+            Some((index, forced)),
+            &mut rng,
+        );
+        iterations += 1;
+    }
+
+    // The same move of this snake can follow any move of the others, so its visits add up
+    let mut visits = [0; 3];
+    for child in root.children.values() {
+        for (total, &(visited, _)) in visits.iter_mut().zip(&child.moves[index]) {
+            *total += visited;
+        }
+    }
+    let (best, _) = visits
+        .iter()
+        .enumerate()
+        .max_by_key(|&(move_index, &visited)| (visited, std::cmp::Reverse(move_index)))?;
+
+    // Where the snake will be facing once the move of this tick is taken
+    let mut after = simulation;
+    after.snakes[index].direction = this_tick;
+    let chosen = after.moves(index)[best];
+    (chosen != this_tick).then_some(chosen)
+}
+
+/// Goes down the tree picking moves, adds a node at the end and returns what it was worth to every snake
+fn iterate(
+    node: &mut Node,
+    simulation: &mut Simulation,
+    depth: usize,
+    forced: Option<(usize, usize)>,
+    rng: &mut ChaCha8Rng,
+) -> Vec<f32> {
+    node.visits += 1;
+    if depth >= MAX_DEPTH {
+        return values(simulation);
+    }
+
+    let joint = node
+        .moves
+        .iter()
+        .enumerate()
+        .map(|(snake, moves)| match forced {
+            Some((index, forced)) if index == snake => forced,
+            _ => select(moves, node.visits),
+        })
+        .collect::<Vec<_>>();
+    let directions = joint
+        .iter()
+        .enumerate()
+        .map(|(snake, &move_index)| simulation.moves(snake)[move_index])
+        .collect::<Vec<_>>();
+    simulation.step(&directions, rng);
+
+    let snakes = node.moves.len();
+    let values = match node.children.get_mut(&joint) {
+        Some(child) => iterate(child, simulation, depth + 1, None, rng),
+        None => {
+            node.children.insert(joint.clone(), Node::new(snakes));
+            playout(simulation, rng);
+            values(simulation)
+        }
+    };
+
+    for ((moves, &move_index), &value) in node.moves.iter_mut().zip(&joint).zip(&values) {
+        moves[move_index].0 += 1;
+        moves[move_index].1 += value;
+    }
+    values
+}
+
+/// Upper confidence bound, every move gets tried once first
+fn select(moves: &[(u32, f32); 3], visits: u32) -> usize {
+    if let Some(untried) = moves.iter().position(|&(visited, _)| visited == 0) {
+        return untried;
+    }
+    let log_visits = (visits as f32).ln();
+    let bound = |&(visited, value): &(u32, f32)| {
+        value / visited as f32 + EXPLORATION * (log_visits / visited as f32).sqrt()
+    };
+    (0..moves.len())
+        .max_by(|&a, &b| bound(&moves[a]).total_cmp(&bound(&moves[b])))
+        .unwrap_or_default()
+}
+
+/// Random moves that don't run into a body straight away, if there are any
+fn playout(simulation: &mut Simulation, rng: &mut ChaCha8Rng) {
+    for _ in 0..PLAYOUT_TICKS {
+        let occupied = simulation.occupied();
+        let directions = (0..simulation.snakes.len())
+            .map(|index| {
+                let moves = simulation.moves(index);
+                let head = simulation.snakes[index].body.front().copied();
+                let free = moves
+                    .iter()
+                    .copied()
+                    .filter(|&direction| {
+                        head.is_some_and(|head| {
+                            !occupied.contains(&neighbor(head, direction, simulation.half_len))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                free.choose(rng)
+                    .or_else(|| moves.choose(rng))
+                    .copied()
+                    .unwrap_or(moves[0])
+            })
+            .collect::<Vec<_>>();
+        simulation.step(&directions, rng);
+    }
+}
+
+fn values(simulation: &Simulation) -> Vec<f32> {
+    let occupied = simulation.occupied();
+    (0..simulation.snakes.len())
+        .map(|index| evaluate(simulation, index, &occupied))
+        .collect()
+}
+
+/// How good the game looks for the snake at `index`, from 0 to 1
+/// Being long compared to the others matters most, then having room to move and then being close to an apple
+fn evaluate(simulation: &Simulation, index: usize, occupied: &HashSet<IVec2>) -> f32 {
+    let snake = &simulation.snakes[index];
+    let Some(&head) = snake.body.front() else {
+        return 0.0;
+    };
+    let length = snake.body.len();
+    let longest_other = simulation
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|&(other, _)| other != index)
+        .map(|(_, other)| other.body.len())
+        .max()
+        .unwrap_or_default();
+    if length >= simulation.length_to_win && length > longest_other {
+        return 1.0;
+    }
+
+    let length_score = length as f32 / (length + longest_other) as f32;
+    let side = 2 * simulation.half_len + 1;
+    let area_score = area(head, occupied, simulation.half_len) as f32 / (side * side) as f32;
+    let apple_score = simulation
+        .apples
+        .iter()
+        .map(|&apple| {
+            let offset = wrapped_offset(head, apple, simulation.half_len);
+            offset.x.abs() + offset.y.abs()
+        })
+        .min()
+        .map_or(0.0, |distance| 1.0 / (1.0 + distance as f32));
+
+    LENGTH_WEIGHT * length_score + AREA_WEIGHT * area_score + APPLE_WEIGHT * apple_score
+}
+
+/// Free cells connected to `head`
+fn area(head: IVec2, occupied: &HashSet<IVec2>, half_len: i32) -> usize {
+    let mut visited = HashSet::from([head]);
+    let mut queue = VecDeque::from([head]);
+    while let Some(cell) = queue.pop_front() {
+        for direction in Direction::ALL {
+            let next = neighbor(cell, direction, half_len);
+            if !occupied.contains(&next) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    visited.len() - 1
+}
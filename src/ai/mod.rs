@@ -0,0 +1,459 @@
+//! Snakes played by the computer
+//! Each of them has a [`Brain`] that picks where to go every tick from a [`GameView`] of the board
+//! Brains are registered by name, see [`RegisterBrain`], and the [`Difficulty`] of the bot says which one it uses,
+//! how far it looks, how fast it reacts and how often it slips
+//! Setting `AI_BRAIN` to the name of a brain gives it to every bot
+//! Presets saved in the `ai_presets` directory, like the ones `snake-evolve` makes, are difficulties too
+
+mod greedy;
+mod heuristic;
+mod mcts;
+mod pathfinding;
+mod random;
+mod simulation;
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::apple::Apple;
+use crate::coordinate::Coordinate;
+use crate::game_state::{AppState, InMatch};
+use crate::headless::Headless;
+use crate::main_menu::{PlayerSlot, PlayerSlots};
+use crate::rng::Seed;
+use crate::rules::GameRules;
+use crate::schedule::{tick_gate_open, MatchSetupSet};
+use crate::snake::Snake;
+use crate::storage::Storage;
+use crate::Direction;
+use crate::ProposeDirection;
+use greedy::Greedy;
+use heuristic::Heuristic;
+pub(crate) use heuristic::Weights;
+use mcts::Mcts;
+use pathfinding::Pathfinding;
+use random::Random;
+
+const BRAIN_VARIABLE: &str = "AI_BRAIN";
+const DEFAULT_BRAIN: &str = "pathfinding";
+pub(crate) const PRESETS_DIRECTORY: &str = "ai_presets";
+
+pub(crate) struct AIPlugin;
+
+impl Plugin for AIPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AiRng(ChaCha8Rng::seed_from_u64(0)))
+            .init_resource::<BrainNames>()
+            .init_resource::<AiPresets>()
+            .register_brain("greedy", |_, _| Box::new(Greedy))
+            .register_brain("heuristic", |settings, _| {
+                Box::new(Heuristic::new(settings))
+            })
+            .register_brain("mcts", |_, seed| Box::new(Mcts::new(seed)))
+            .register_brain("pathfinding", |settings, _| {
+                Box::new(Pathfinding::new(settings))
+            })
+            .register_brain("random", |_, seed| Box::new(Random::new(seed)))
+            .add_systems(Startup, load_ai_presets)
+            .add_systems(OnEnter(InMatch), seed_ai_rng.in_set(MatchSetupSet::Spawn))
+            // Before the tick like any other input, so the AI sees the board the move is made on
+            // and replays record its moves for the right tick
+            .add_systems(
+                FixedFirst,
+                (attach_brains, think)
+                    .chain()
+                    .run_if(in_state(AppState::InGame).and(tick_gate_open)),
+            );
+    }
+}
+
+/// Decides where a snake goes
+pub(crate) trait SnakeBrain: Send + Sync {
+    /// Direction the snake with `id` should turn to, `None` to keep going
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction>;
+}
+
+/// The board as a brain sees it, cells go from `-half_len` to `half_len` in both axes and wrap around
+pub(crate) struct GameView {
+    pub(crate) half_len: i32,
+    /// Length the longest snake needs to win
+    pub(crate) length_to_win: usize,
+    /// Ticks a snake goes through others after a hit
+    pub(crate) inmortal_ticks: u8,
+    /// Part of the snake lost on each hit
+    pub(crate) proportion_lost_per_hit: f32,
+    /// By id
+    pub(crate) snakes: Vec<SnakeView>,
+    pub(crate) apples: Vec<IVec2>,
+    /// Ticks run as soon as the last one is done instead of following the clock, see [`Headless`]
+    pub(crate) headless: bool,
+}
+
+pub(crate) struct SnakeView {
+    pub(crate) id: u8,
+    pub(crate) direction: Direction,
+    /// Head first
+    pub(crate) body: Vec<IVec2>,
+    /// Ticks left of going through other snakes
+    pub(crate) inmortal_ticks: u8,
+}
+
+impl GameView {
+    pub(crate) fn snake(&self, id: u8) -> Option<&SnakeView> {
+        self.snakes.iter().find(|snake| snake.id == id)
+    }
+}
+
+type BrainConstructor = Box<dyn Fn(&DifficultySettings, u64) -> Box<dyn SnakeBrain> + Send + Sync>;
+
+/// Every brain a bot can use, by name
+#[derive(Resource, Default)]
+pub(crate) struct BrainRegistry(BTreeMap<String, BrainConstructor>);
+
+impl BrainRegistry {
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+}
+
+/// Brains picked for some players instead of the one of their difficulty, by player number
+#[derive(Resource, Default)]
+pub(crate) struct BrainNames(pub(crate) BTreeMap<u8, String>);
+
+pub(crate) trait RegisterBrain {
+    /// Makes a brain available as `name`, built from the difficulty of the bot and a seed for its randomness
+    fn register_brain(
+        &mut self,
+        name: &str,
+        constructor: impl Fn(&DifficultySettings, u64) -> Box<dyn SnakeBrain> + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl RegisterBrain for App {
+    fn register_brain(
+        &mut self,
+        name: &str,
+        constructor: impl Fn(&DifficultySettings, u64) -> Box<dyn SnakeBrain> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.init_resource::<BrainRegistry>();
+        self.world_mut()
+            .resource_mut::<BrainRegistry>()
+            .0
+            .insert(name.to_string(), Box::new(constructor));
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    /// One of the [`AiPresets`], by name
+    Preset(String),
+}
+
+impl Difficulty {
+    pub(crate) const ALL: [Difficulty; 3] =
+        [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub(crate) fn settings(&self, presets: &AiPresets) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                brain: DEFAULT_BRAIN.to_string(),
+                lookahead: 6,
+                reaction_delay: 2,
+                error_rate: 0.1,
+                aggression: 0.2,
+                weights: Weights::default(),
+            },
+            Difficulty::Medium => DifficultySettings {
+                brain: DEFAULT_BRAIN.to_string(),
+                lookahead: 14,
+                reaction_delay: 1,
+                error_rate: 0.03,
+                aggression: 0.5,
+                weights: Weights::default(),
+            },
+            Difficulty::Hard => DifficultySettings {
+                brain: DEFAULT_BRAIN.to_string(),
+                // The whole board
+                lookahead: usize::MAX,
+                reaction_delay: 0,
+                error_rate: 0.0,
+                aggression: 0.8,
+                weights: Weights::default(),
+            },
+            Difficulty::Preset(name) => presets.0.get(name).cloned().unwrap_or_else(|| {
+                warn!("There is no AI preset called `{}`", name);
+                Difficulty::Medium.settings(presets)
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Preset(name) => write!(f, "{}", name),
+            difficulty => write!(f, "{:?}", difficulty),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct DifficultySettings {
+    /// Name of the brain, see [`RegisterBrain`]
+    pub(crate) brain: String,
+    /// How many cells ahead the snake looks for apples and for room to move
+    pub(crate) lookahead: usize,
+    /// Ticks between seeing the board and turning
+    pub(crate) reaction_delay: usize,
+    /// Probability of turning somewhere random instead
+    pub(crate) error_rate: f64,
+    /// From 0 to 1, aggressive snakes chase apples into tighter spots and don't give way to shorter snakes
+    pub(crate) aggression: f32,
+    /// For the `heuristic` brain
+    #[serde(default)]
+    pub(crate) weights: Weights,
+}
+
+impl DifficultySettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.lookahead == 0 {
+            return Err("The lookahead has to be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.error_rate) {
+            return Err("The error rate has to be from 0 to 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.aggression) {
+            return Err("The aggression has to be from 0 to 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Difficulties saved as RON files, by name
+#[derive(Resource, Default, Clone)]
+pub(crate) struct AiPresets(pub(crate) BTreeMap<String, DifficultySettings>);
+
+impl AiPresets {
+    pub(crate) fn load(storage: &Storage) -> Self {
+        Self(
+            storage
+                .list(PRESETS_DIRECTORY)
+                .into_iter()
+                .filter_map(|key| {
+                    let settings = storage.load::<DifficultySettings>(&key)?;
+                    // Out of range settings would make the snake crash the game instead
+                    if let Err(error) = settings.validate() {
+                        warn!("Ignoring the AI preset `{}`: {}", key, error);
+                        return None;
+                    }
+                    let name = key
+                        .trim_start_matches(PRESETS_DIRECTORY)
+                        .trim_start_matches('/');
+                    Some((name.to_string(), settings))
+                })
+                .collect(),
+        )
+    }
+}
+
+fn load_ai_presets(mut presets: ResMut<AiPresets>, storage: Res<Storage>) {
+    *presets = AiPresets::load(&storage);
+}
+
+/// Randomness of the AI, apart from the game rules so a replay gets the same apples without the AI running
+#[derive(Resource)]
+struct AiRng(ChaCha8Rng);
+
+fn seed_ai_rng(seed: Res<Seed>, mut rng: ResMut<AiRng>) {
+    rng.0 = ChaCha8Rng::seed_from_u64(seed.0);
+    rng.0.set_stream(1);
+}
+
+/// Decides where an AI snake goes, with the reaction delay and the slips of its difficulty on top
+#[derive(Component)]
+pub(crate) struct Brain {
+    mind: Box<dyn SnakeBrain>,
+    reaction_delay: usize,
+    error_rate: f64,
+    /// Decisions not reacted to yet
+    reactions: VecDeque<Option<Direction>>,
+}
+
+fn attach_brains(
+    mut commands: Commands,
+    snakes: Query<(Entity, &Snake), Without<Brain>>,
+    player_slots: Res<PlayerSlots>,
+    registry: Res<BrainRegistry>,
+    brain_names: Res<BrainNames>,
+    presets: Res<AiPresets>,
+    mut rng: ResMut<AiRng>,
+) {
+    // In player order, so the AI rng is used the same way every time
+    let mut snakes = snakes.iter().collect::<Vec<_>>();
+    snakes.sort_by_key(|(_, snake)| snake.player_number.0);
+
+    for (entity, snake) in snakes {
+        let PlayerSlot::Bot(difficulty) = player_slots.player(snake.player_number.0) else {
+            continue;
+        };
+        let mut settings = difficulty.settings(&presets);
+        if let Some(brain) = brain_names.0.get(&snake.player_number.0) {
+            settings.brain = brain.clone();
+        } else if let Ok(brain) = std::env::var(BRAIN_VARIABLE) {
+            settings.brain = brain;
+        }
+
+        let constructor = registry.0.get(&settings.brain).or_else(|| {
+            warn!(
+                "There is no brain called `{}`, try one of {:?}",
+                settings.brain,
+                registry.0.keys().collect::<Vec<_>>()
+            );
+            registry.0.get(DEFAULT_BRAIN)
+        });
+        let Some(constructor) = constructor else {
+            continue;
+        };
+        commands.entity(entity).insert(Brain {
+            mind: constructor(&settings, rng.0.gen()),
+            reaction_delay: settings.reaction_delay,
+            error_rate: settings.error_rate,
+            reactions: VecDeque::new(),
+        });
+    }
+}
+
+fn cell(coordinate: &Coordinate) -> IVec2 {
+    coordinate.0.round().as_ivec2()
+}
+
+/// The cell next to `cell` in `direction`, wrapping around the edges like the snakes do
+fn neighbor(cell: IVec2, direction: Direction, half_len: i32) -> IVec2 {
+    let offset: Vec2 = direction.into();
+    let side = 2 * half_len + 1;
+    (cell + offset.as_ivec2() + half_len).rem_euclid(IVec2::splat(side)) - half_len
+}
+
+/// The shortest way from `from` to `to`, which might go around the edges
+fn wrapped_offset(from: IVec2, to: IVec2, half_len: i32) -> IVec2 {
+    let side = 2 * half_len + 1;
+    (to - from + half_len).rem_euclid(IVec2::splat(side)) - half_len
+}
+
+#[allow(clippy::too_many_arguments)]
+fn think(
+    mut brains: Query<(&Snake, &mut Brain)>,
+    snakes: Query<&Snake>,
+    apples: Query<&Coordinate, With<Apple>>,
+    coordinates: Query<&Coordinate>,
+    rules: Res<GameRules>,
+    headless: Option<Res<Headless>>,
+    mut rng: ResMut<AiRng>,
+    mut propose_direction: MessageWriter<ProposeDirection>,
+) {
+    let mut snakes = snakes
+        .iter()
+        .map(|snake| SnakeView {
+            id: snake.player_number.0,
+            direction: snake.direction,
+            body: snake
+                .segments
+                .iter()
+                .filter_map(|&segment| coordinates.get(segment).ok())
+                .map(cell)
+                .collect(),
+            inmortal_ticks: snake.inmortal_ticks,
+        })
+        .collect::<Vec<_>>();
+    snakes.sort_by_key(|snake| snake.id);
+    let view = GameView {
+        half_len: rules.board.half_len,
+        length_to_win: rules.length_to_win,
+        inmortal_ticks: rules.inmortal_ticks,
+        proportion_lost_per_hit: rules.proportion_lost_per_hit,
+        snakes,
+        apples: apples.iter().map(cell).collect(),
+        headless: headless.is_some(),
+    };
+
+    let mut brains = brains.iter_mut().collect::<Vec<_>>();
+    brains.sort_by_key(|(snake, _)| snake.player_number.0);
+
+    for (snake, mut brain) in brains {
+        let mut decision = brain.mind.think(&view, snake.player_number.0);
+        if rng.0.gen_bool(brain.error_rate) {
+            let turns = Direction::ALL
+                .into_iter()
+                .filter(|&d| d != !snake.direction)
+                .collect::<Vec<_>>();
+            decision = turns.choose(&mut rng.0).copied();
+        }
+
+        brain.reactions.push_back(decision);
+        while brain.reactions.len() > brain.reaction_delay {
+            let Some(decision) = brain.reactions.pop_front() else {
+                break;
+            };
+            match decision {
+                Some(direction)
+                    if direction != snake.direction && snake.next_directions.is_empty() =>
+                {
+                    propose_direction.write(ProposeDirection {
+                        id: snake.player_number.clone(),
+                        direction,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_out_of_range_are_ignored() {
+        let storage = Storage::in_memory();
+        let valid = Difficulty::Medium.settings(&AiPresets::default());
+        storage.save(&format!("{}/valid", PRESETS_DIRECTORY), &valid);
+        for (name, settings) in [
+            (
+                "clumsy",
+                DifficultySettings {
+                    error_rate: 1.5,
+                    ..valid.clone()
+                },
+            ),
+            (
+                "furious",
+                DifficultySettings {
+                    aggression: -0.5,
+                    ..valid.clone()
+                },
+            ),
+            (
+                "blind",
+                DifficultySettings {
+                    lookahead: 0,
+                    ..valid.clone()
+                },
+            ),
+        ] {
+            storage.save(&format!("{}/{}", PRESETS_DIRECTORY, name), &settings);
+        }
+
+        let presets = AiPresets::load(&storage);
+        assert_eq!(presets.0.keys().collect::<Vec<_>>(), ["valid"]);
+        assert_eq!(presets.0["valid"], valid);
+    }
+}
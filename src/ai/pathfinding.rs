@@ -0,0 +1,269 @@
+//! Walks the shortest path to the nearest apple it can reach,
+//! unless following it would trap the snake, then heads wherever there is the most room left
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use super::{neighbor, DifficultySettings, GameView, SnakeBrain};
+use crate::Direction;
+
+pub(super) struct Pathfinding {
+    /// How many cells ahead the snake looks for apples and for room to move
+    lookahead: usize,
+    aggression: f32,
+}
+
+impl Pathfinding {
+    pub(super) fn new(settings: &DifficultySettings) -> Self {
+        Self {
+            lookahead: settings.lookahead,
+            aggression: settings.aggression,
+        }
+    }
+}
+
+impl SnakeBrain for Pathfinding {
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction> {
+        let snake = view.snake(id)?;
+        let &head = snake.body.first()?;
+        Board::new(view).choose(head, snake.direction, self.lookahead, self.aggression)
+    }
+}
+
+/// The board as cells, with how long each of them stays occupied
+struct Board<'a> {
+    view: &'a GameView,
+    /// Ticks until each occupied cell is left behind by the tail moving forward
+    vacated_in: HashMap<IVec2, u32>,
+}
+
+impl<'a> Board<'a> {
+    fn new(view: &'a GameView) -> Self {
+        let mut vacated_in = HashMap::new();
+        for body in view.snakes.iter().map(|snake| &snake.body) {
+            for (index, &cell) in body.iter().enumerate() {
+                let ticks = (body.len() - index) as u32;
+                vacated_in
+                    .entry(cell)
+                    .and_modify(|vacated: &mut u32| *vacated = (*vacated).max(ticks))
+                    .or_insert(ticks);
+            }
+        }
+        Self { view, vacated_in }
+    }
+
+    /// Whether a head getting to `cell` in `ticks` wouldn't hit a body
+    fn is_free(&self, cell: IVec2, ticks: u32) -> bool {
+        self.vacated_in
+            .get(&cell)
+            .is_none_or(|&vacated| ticks >= vacated)
+    }
+
+    /// Directions that don't hit anything right away
+    /// The ones that could meet another head are only left when there is nothing else,
+    /// or when the other snake is shorter and this one is aggressive
+    fn first_moves(
+        &self,
+        head: IVec2,
+        direction: Direction,
+        aggression: f32,
+    ) -> Vec<(Direction, IVec2)> {
+        let length = self.length(head);
+        let other_heads_next = self
+            .view
+            .snakes
+            .iter()
+            .map(|snake| &snake.body)
+            .filter(|body| body.first().is_some_and(|&other| other != head))
+            .filter(|body| aggression < 0.5 || body.len() >= length)
+            .filter_map(|body| body.first())
+            .flat_map(|&other| {
+                Direction::ALL.map(|direction| neighbor(other, direction, self.view.half_len))
+            })
+            .collect::<HashSet<_>>();
+
+        // Going straight first, so ties don't make the snake wiggle
+        let moves = std::iter::once(direction)
+            .chain(Direction::ALL.into_iter().filter(|&d| d != direction))
+            .filter(|&d| d != !direction)
+            .map(|d| (d, neighbor(head, d, self.view.half_len)))
+            .filter(|&(_, cell)| self.is_free(cell, 1))
+            .collect::<Vec<_>>();
+        let safe = moves
+            .iter()
+            .copied()
+            .filter(|(_, cell)| !other_heads_next.contains(cell))
+            .collect::<Vec<_>>();
+        if safe.is_empty() {
+            moves
+        } else {
+            safe
+        }
+    }
+
+    fn length(&self, head: IVec2) -> usize {
+        self.view
+            .snakes
+            .iter()
+            .find(|snake| snake.body.first() == Some(&head))
+            .map_or(1, |snake| snake.body.len())
+    }
+
+    /// Direction of the shortest path to the nearest apple reachable within `lookahead` cells and its length
+    fn path_to_apple(
+        &self,
+        moves: &[(Direction, IVec2)],
+        lookahead: usize,
+    ) -> Option<(Direction, u32)> {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        for &(direction, cell) in moves {
+            if visited.insert(cell) {
+                queue.push_back((cell, direction, 1));
+            }
+        }
+
+        while let Some((cell, first, ticks)) = queue.pop_front() {
+            if self.view.apples.contains(&cell) {
+                return Some((first, ticks));
+            }
+            if ticks as usize >= lookahead {
+                continue;
+            }
+            for direction in Direction::ALL {
+                let next = neighbor(cell, direction, self.view.half_len);
+                if self.is_free(next, ticks + 1) && visited.insert(next) {
+                    queue.push_back((next, first, ticks + 1));
+                }
+            }
+        }
+        None
+    }
+
+    /// Cells reachable after getting to `cell`, counting up to `limit`
+    fn room(&self, cell: IVec2, limit: usize) -> usize {
+        let mut visited = HashSet::from([cell]);
+        let mut queue = VecDeque::from([(cell, 1)]);
+        while let Some((cell, ticks)) = queue.pop_front() {
+            if visited.len() >= limit {
+                break;
+            }
+            for direction in Direction::ALL {
+                let next = neighbor(cell, direction, self.view.half_len);
+                if self.is_free(next, ticks + 1) && visited.insert(next) {
+                    queue.push_back((next, ticks + 1));
+                }
+            }
+        }
+        visited.len()
+    }
+
+    /// Where the snake with this head should go next, `None` when every direction is blocked
+    fn choose(
+        &self,
+        head: IVec2,
+        direction: Direction,
+        lookahead: usize,
+        aggression: f32,
+    ) -> Option<Direction> {
+        let length = self.length(head);
+        let moves = self.first_moves(head, direction, aggression);
+
+        // An apple is only worth it if there is room to keep going after it
+        if let Some((to_apple, _)) = self.path_to_apple(&moves, lookahead) {
+            let cell = moves
+                .iter()
+                .find(|&&(d, _)| d == to_apple)
+                .map(|&(_, cell)| cell)?;
+            let wanted_room = ((2.0 - aggression) * length as f32).ceil() as usize;
+            let wanted_room = wanted_room.clamp(1, lookahead);
+            if self.room(cell, wanted_room) >= wanted_room {
+                return Some(to_apple);
+            }
+        }
+
+        // Trapped or no apple in reach, survive as long as possible
+        moves
+            .iter()
+            .map(|&(d, cell)| (d, self.room(cell, lookahead)))
+            .fold(
+                None,
+                |best: Option<(Direction, usize)>, (d, room)| match best {
+                    Some((_, best_room)) if best_room >= room => best,
+                    _ => Some((d, room)),
+                },
+            )
+            .map(|(d, _)| d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::SnakeView;
+
+    fn view(snakes: Vec<SnakeView>, apples: Vec<IVec2>) -> GameView {
+        GameView {
+            half_len: 7,
+            length_to_win: 10,
+            inmortal_ticks: 10,
+            proportion_lost_per_hit: 0.3,
+            snakes,
+            apples,
+            headless: true,
+        }
+    }
+
+    fn snake(id: u8, direction: Direction, body: &[(i32, i32)]) -> SnakeView {
+        SnakeView {
+            id,
+            direction,
+            body: body.iter().map(|&(x, y)| IVec2::new(x, y)).collect(),
+            inmortal_ticks: 0,
+        }
+    }
+
+    fn pathfinding() -> Pathfinding {
+        Pathfinding {
+            lookahead: 14,
+            aggression: 0.5,
+        }
+    }
+
+    #[test]
+    fn the_path_wraps_around_the_edge_when_that_is_shorter() {
+        let view = view(
+            vec![snake(1, Direction::Up, &[(-6, 0), (-6, -1)])],
+            vec![IVec2::new(6, 0)],
+        );
+        assert_eq!(pathfinding().think(&view, 1), Some(Direction::Left));
+    }
+
+    #[test]
+    fn cells_still_occupied_next_tick_are_avoided() {
+        // The apple is right behind the body of the other snake, which stays there for 2 more ticks
+        let view = view(
+            vec![
+                snake(1, Direction::Right, &[(0, 0), (-1, 0)]),
+                snake(2, Direction::Up, &[(1, 3), (1, 2), (1, 1), (1, 0), (1, -1)]),
+            ],
+            vec![IVec2::new(2, 0)],
+        );
+        let direction = pathfinding().think(&view, 1);
+        assert!(direction.is_some());
+        assert_ne!(direction, Some(Direction::Right));
+    }
+
+    #[test]
+    fn tails_leave_their_cell_in_time() {
+        let view = view(
+            vec![
+                snake(1, Direction::Right, &[(0, 0), (-1, 0)]),
+                snake(2, Direction::Up, &[(1, 3), (1, 2), (1, 1), (1, 0)]),
+            ],
+            vec![IVec2::new(2, 0)],
+        );
+        assert_eq!(pathfinding().think(&view, 1), Some(Direction::Right));
+    }
+}
@@ -0,0 +1,26 @@
+//! Turns anywhere but back, at random
+
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{GameView, SnakeBrain};
+use crate::Direction;
+
+pub(super) struct Random(ChaCha8Rng);
+
+impl Random {
+    pub(super) fn new(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+impl SnakeBrain for Random {
+    fn think(&mut self, view: &GameView, id: u8) -> Option<Direction> {
+        let snake = view.snake(id)?;
+        let turns = Direction::ALL
+            .into_iter()
+            .filter(|&direction| direction != !snake.direction)
+            .collect::<Vec<_>>();
+        turns.choose(&mut self.0).copied()
+    }
+}
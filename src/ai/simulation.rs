@@ -0,0 +1,144 @@
+//! The game rules on plain cells, cheap enough to clone and play thousands of ticks ahead
+//! It has to do the same as the systems in `movement`, `apple` and `collision`
+
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::{neighbor, GameView};
+use crate::Direction;
+
+#[derive(Clone)]
+pub(super) struct Simulation {
+    pub(super) half_len: i32,
+    pub(super) length_to_win: usize,
+    pub(super) inmortal_ticks: u8,
+    pub(super) proportion_lost_per_hit: f32,
+    /// By id
+    pub(super) snakes: Vec<SimulatedSnake>,
+    pub(super) apples: Vec<IVec2>,
+}
+
+#[derive(Clone)]
+pub(super) struct SimulatedSnake {
+    pub(super) id: u8,
+    pub(super) direction: Direction,
+    /// Head first
+    pub(super) body: VecDeque<IVec2>,
+    pub(super) inmortal_ticks: u8,
+}
+
+impl Simulation {
+    pub(super) fn new(view: &GameView) -> Self {
+        Self {
+            half_len: view.half_len,
+            length_to_win: view.length_to_win,
+            inmortal_ticks: view.inmortal_ticks,
+            proportion_lost_per_hit: view.proportion_lost_per_hit,
+            snakes: view
+                .snakes
+                .iter()
+                .map(|snake| SimulatedSnake {
+                    id: snake.id,
+                    direction: snake.direction,
+                    body: snake.body.iter().copied().collect(),
+                    inmortal_ticks: snake.inmortal_ticks,
+                })
+                .collect(),
+            apples: view.apples.clone(),
+        }
+    }
+
+    /// Where the snake at `index` can go, straight first and anything but back
+    pub(super) fn moves(&self, index: usize) -> [Direction; 3] {
+        let direction = self.snakes[index].direction;
+        let mut moves = [direction; 3];
+        for (slot, turn) in moves[1..].iter_mut().zip(
+            Direction::ALL
+                .into_iter()
+                .filter(|&d| d != direction && d != !direction),
+        ) {
+            *slot = turn;
+        }
+        moves
+    }
+
+    /// Every cell with a snake on it
+    pub(super) fn occupied(&self) -> HashSet<IVec2> {
+        self.snakes
+            .iter()
+            .flat_map(|snake| snake.body.iter().copied())
+            .collect()
+    }
+
+    /// One tick, with the direction of each snake in the same order as `snakes`
+    pub(super) fn step(&mut self, directions: &[Direction], rng: &mut impl Rng) {
+        // Movement, the tail goes in front of the head
+        let mut trails = Vec::with_capacity(self.snakes.len());
+        for (snake, &direction) in self.snakes.iter_mut().zip(directions) {
+            if direction != !snake.direction {
+                snake.direction = direction;
+            }
+            let Some(&head) = snake.body.front() else {
+                trails.push(None);
+                continue;
+            };
+            snake
+                .body
+                .push_front(neighbor(head, snake.direction, self.half_len));
+            trails.push(snake.body.pop_back());
+        }
+
+        // Eating, the new apples only show up after every snake had its chance
+        let mut eaten = 0;
+        for (snake, trail) in self.snakes.iter_mut().zip(trails) {
+            let Some(head) = snake.body.front() else {
+                continue;
+            };
+            if let Some(index) = self.apples.iter().position(|apple| apple == head) {
+                self.apples.swap_remove(index);
+                snake.body.extend(trail);
+                eaten += 1;
+            }
+        }
+        for _ in 0..eaten {
+            self.apples.push(IVec2::new(
+                rng.gen_range(-self.half_len..self.half_len),
+                rng.gen_range(-self.half_len..self.half_len),
+            ));
+        }
+
+        // Collisions
+        for snake in self.snakes.iter_mut() {
+            snake.inmortal_ticks = snake.inmortal_ticks.saturating_sub(1);
+        }
+        let bodies = self
+            .snakes
+            .iter()
+            .flat_map(|snake| snake.body.iter().skip(1).copied())
+            .collect::<HashSet<_>>();
+        let heads = self
+            .snakes
+            .iter()
+            .map(|snake| snake.body.front().copied())
+            .collect::<Vec<_>>();
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            let Some(head) = heads[index].filter(|_| snake.inmortal_ticks == 0) else {
+                continue;
+            };
+            let hit = bodies.contains(&head)
+                || heads
+                    .iter()
+                    .enumerate()
+                    .any(|(other, &other_head)| other != index && other_head == Some(head));
+            if hit {
+                let length = snake.body.len();
+                let lost = (length - 1)
+                    .min((length as f32 * self.proportion_lost_per_hit).ceil() as usize);
+                snake.body.truncate(length - lost);
+                snake.inmortal_ticks = self.inmortal_ticks;
+            }
+        }
+    }
+}
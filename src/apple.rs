@@ -4,50 +4,73 @@ use rand::Rng;
 use crate::snake::Tile;
 
 use super::{
-    asset_loader::SceneAssets,
     coordinate::Coordinate,
-    game_state::AppState,
-    schedule::InGameSet,
+    game_state::InMatch,
+    rng::{seed_rng, GameRng},
+    rules::GameRules,
+    schedule::{InGameSet, MatchSetupSet, TickSet},
     snake::{Depth, Snake},
-    HALF_LEN,
+    theme::ActiveTheme,
 };
 
+pub(crate) const NUMBER_OF_APPLES: usize = 4;
+
 pub(crate) struct ApplePlugin;
 
 impl Plugin for ApplePlugin {
     fn build(&self, app: &mut App) {
         app.add_message::<AppleEaten>()
-            .add_systems(Startup, setup)
+            .add_systems(
+                OnEnter(InMatch),
+                (despawn_apples, spawn_apples)
+                    .chain()
+                    .after(seed_rng)
+                    .in_set(MatchSetupSet::Spawn),
+            )
+            .add_systems(FixedUpdate, eat_apple.in_set(TickSet::Eat))
             .add_systems(
                 Update,
-                eat_apple
+                add_apple_sprites
                     .in_set(InGameSet::SpawnDespawnEntities)
-                    .run_if(in_state(AppState::InGame)),
+                    .run_if(in_state(InMatch).and(resource_exists::<ActiveTheme>)),
             );
     }
 }
 
-fn setup(mut commands: Commands, assets: Res<SceneAssets>) {
-    spawn_apple(&mut commands, &assets);
-    spawn_apple(&mut commands, &assets);
-    spawn_apple(&mut commands, &assets);
-    spawn_apple(&mut commands, &assets);
+fn despawn_apples(mut commands: Commands, apples: Query<Entity, With<Apple>>) {
+    for apple in apples.iter() {
+        commands.entity(apple).despawn();
+    }
+}
+
+fn spawn_apples(mut commands: Commands, mut rng: ResMut<GameRng>, rules: Res<GameRules>) {
+    for _ in 0..NUMBER_OF_APPLES {
+        spawn_apple(&mut commands, &mut rng, rules.board.half_len);
+    }
+}
+
+fn spawn_apple(commands: &mut Commands, rng: &mut GameRng, half_len: i32) {
+    commands.spawn(apple(Coordinate(Vec2::new(
+        rng.0.gen_range(-half_len..half_len) as f32,
+        rng.0.gen_range(-half_len..half_len) as f32,
+    ))));
+}
+
+pub(crate) fn apple(coordinate: Coordinate) -> impl Bundle {
+    (Apple, Depth(1.0), coordinate, Tile)
 }
 
-fn spawn_apple(commands: &mut Commands, assets: &Res<SceneAssets>) {
-    commands.spawn((
-        Apple,
-        Depth(1.0),
-        Coordinate(Vec2::new(
-            rand::thread_rng().gen_range(-HALF_LEN..HALF_LEN) as f32,
-            rand::thread_rng().gen_range(-HALF_LEN..HALF_LEN) as f32,
-        )),
-        Sprite {
-            image: assets.apple.clone(),
+fn add_apple_sprites(
+    mut commands: Commands,
+    apples: Query<Entity, (With<Apple>, Without<Sprite>)>,
+    theme: Res<ActiveTheme>,
+) {
+    for apple in apples.iter() {
+        commands.entity(apple).insert(Sprite {
+            image: theme.0.random_food(),
             ..default()
-        },
-        Tile,
-    ));
+        });
+    }
 }
 
 #[derive(Component)]
@@ -58,10 +81,11 @@ pub(crate) struct AppleEaten(pub(crate) Entity);
 
 fn eat_apple(
     mut commands: Commands,
-    mut snakes: Query<(Entity, &mut Snake)>,
+    snakes: Query<(Entity, &Snake)>,
     coordinates: Query<&Coordinate>,
     apples: Query<(Entity, &Coordinate), With<Apple>>,
-    assets: Res<SceneAssets>,
+    mut rng: ResMut<GameRng>,
+    rules: Res<GameRules>,
     mut apple_eaten: MessageWriter<AppleEaten>,
 ) {
     let get_head = |snake: &Snake| {
@@ -69,15 +93,25 @@ fn eat_apple(
         coordinates.get(head).ok()
     };
 
-    for (entity, snake) in snakes.iter_mut() {
-        for (apple, coord) in apples.iter() {
-            if Some(coord) == get_head(&snake) {
-                // The despawn and spawn could be handled by events, but that would require configuring ordering in order to make sure we don't get to an inconsistent state. https://bevy-cheatbook.github.io/programming/events.html#possible-pitfalls
-                commands.entity(apple).despawn();
-                spawn_apple(&mut commands, &assets);
-                apple_eaten.write(AppleEaten(entity));
-                return;
-            }
+    // Entities and query order change when the game is restored from a snapshot,
+    // so snakes go in player order for the new apples to come out of the rng the same way
+    let mut snakes = snakes.iter().collect::<Vec<_>>();
+    snakes.sort_by_key(|(_, snake)| snake.player_number.0);
+
+    let mut eaten = Vec::new();
+    for (entity, snake) in snakes {
+        let Some(head) = get_head(snake) else {
+            continue;
+        };
+        let apple = apples
+            .iter()
+            .find(|(apple, coordinate)| *coordinate == head && !eaten.contains(apple));
+        if let Some((apple, _)) = apple {
+            // The despawn and spawn could be handled by events, but that would require configuring ordering in order to make sure we don't get to an inconsistent state. https://bevy-cheatbook.github.io/programming/events.html#possible-pitfalls
+            commands.entity(apple).despawn();
+            eaten.push(apple);
+            spawn_apple(&mut commands, &mut rng, rules.board.half_len);
+            apple_eaten.write(AppleEaten(entity));
         }
     }
 }
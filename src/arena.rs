@@ -0,0 +1,286 @@
+//! Matches between bots without a window, as fast as they can think
+//! Run with `snake-arena [--matches N] [--seed S] [--max-ticks T] [--time-budget MS] [--rules FILE] [--map FILE] BOT BOT...`
+//! Each bot is a brain like `mcts` or `pathfinding:easy`, or the command or Battlesnake URL of an external bot
+//! The difficulty can also be the name of an AI preset, like `heuristic:evolved`
+//! Every match is saved as a replay that can be watched from the game
+
+use std::collections::BTreeMap;
+
+use bevy::{ecs::message::MessageCursor, prelude::*};
+
+use crate::{
+    ai::{AIPlugin, AiPresets, BrainNames, BrainRegistry, Difficulty},
+    bots::{start_headless_bots, BotsPlugin, RunningBots},
+    collision::Collision,
+    headless::{headless_app_with, play_until_won},
+    launch::number,
+    main_menu::{PlayerSlot, PlayerSlots},
+    replay::{add_recording_systems, recorded, Replay, REPLAYS_DIRECTORY},
+    rules::{Board, GameRules},
+    snake::Snake,
+    storage::Storage,
+    MAX_NUMBER_OF_PLAYERS,
+};
+
+const USAGE: &str =
+    "Usage: snake-arena [--matches N] [--seed S] [--max-ticks T] [--time-budget MS] [--rules FILE] [--map FILE] BOT BOT...
+Each bot is a brain like `mcts` or `pathfinding:easy`, or the command or URL of an external bot";
+
+struct Settings {
+    matches: u32,
+    /// Match `i` is played with `seed + i`
+    seed: u64,
+    /// A match nobody won by then is a draw
+    max_ticks: u32,
+    time_budget_ms: u64,
+    /// The default ones unless given a rules file, with the board of the map on top
+    rules: GameRules,
+    bots: Vec<BotSpec>,
+    presets: AiPresets,
+}
+
+enum BotSpec {
+    Brain {
+        name: String,
+        difficulty: Difficulty,
+    },
+    External(String),
+}
+
+impl std::fmt::Display for BotSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotSpec::Brain { name, difficulty } => write!(f, "{} ({})", name, difficulty),
+            BotSpec::External(command) => write!(f, "{}", command),
+        }
+    }
+}
+
+/// How a player did over every match
+#[derive(Default)]
+struct Results {
+    wins: u32,
+    /// Summed over every match, at the end of each
+    length: usize,
+    collisions: u32,
+    timeouts: u32,
+}
+
+/// Plays every match and reports how each bot did
+pub fn run(arguments: impl Iterator<Item = String>) -> Result<String, String> {
+    let storage = Storage::platform_default();
+    let settings = parse(arguments, AiPresets::load(&storage))?;
+    let mut results = settings
+        .bots
+        .iter()
+        .map(|_| Results::default())
+        .collect::<Vec<_>>();
+    let mut draws = 0;
+
+    for index in 0..settings.matches {
+        let seed = settings.seed.wrapping_add(u64::from(index));
+        let (winner, replay) = play(&settings, seed, &mut results);
+        if let Some(player) = winner {
+            results[player].wins += 1;
+        } else {
+            draws += 1;
+        }
+        storage.save(
+            &format!("{}/arena-{:016x}", REPLAYS_DIRECTORY, seed),
+            &replay,
+        );
+        let outcome = match winner {
+            Some(player) => format!("player {} won", player + 1),
+            None => "draw".to_string(),
+        };
+        println!(
+            "Match {} with seed {}: {} after {} ticks",
+            index + 1,
+            seed,
+            outcome,
+            replay.ticks
+        );
+    }
+
+    let matches = settings.matches.max(1) as f32;
+    let mut table = format!(
+        "{:<7} {:<30} {:>5} {:>11} {:>10} {:>8}\n",
+        "Player", "Bot", "Wins", "Avg length", "Collisions", "Timeouts"
+    );
+    for (index, (bot, results)) in settings.bots.iter().zip(results.iter()).enumerate() {
+        table.push_str(&format!(
+            "{:<7} {:<30} {:>5} {:>11.1} {:>10} {:>8}\n",
+            index + 1,
+            bot.to_string(),
+            results.wins,
+            results.length as f32 / matches,
+            results.collisions,
+            results.timeouts
+        ));
+    }
+    table.push_str(&format!(
+        "{} matches from seed {}, {} draws",
+        settings.matches, settings.seed, draws
+    ));
+    Ok(table)
+}
+
+fn parse(
+    mut arguments: impl Iterator<Item = String>,
+    presets: AiPresets,
+) -> Result<Settings, String> {
+    // Only to know which names are brains
+    let mut brains = App::new();
+    brains.add_plugins(AIPlugin);
+    let registry = brains.world().resource::<BrainRegistry>();
+
+    let mut settings = Settings {
+        matches: 10,
+        seed: rand::random(),
+        max_ticks: 3000,
+        time_budget_ms: 50,
+        rules: GameRules::default(),
+        bots: Vec::new(),
+        presets,
+    };
+    let mut map = None;
+    // The name of the program
+    arguments.next();
+    while let Some(argument) = arguments.next() {
+        let mut value = |name: &str| {
+            arguments
+                .next()
+                .ok_or_else(|| format!("Missing the value of {}\n{}", name, USAGE))
+        };
+        match argument.as_str() {
+            "--matches" => settings.matches = number(&value("--matches")?)?,
+            "--seed" => settings.seed = number(&value("--seed")?)?,
+            "--max-ticks" => settings.max_ticks = number(&value("--max-ticks")?)?,
+            "--time-budget" => settings.time_budget_ms = number(&value("--time-budget")?)?,
+            "--rules" => settings.rules = rules_file(&value("--rules")?)?,
+            "--map" => map = Some(Board::load(&value("--map")?)?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => {
+                let bot = bot_spec(&argument, registry, &settings.presets)?;
+                settings.bots.push(bot);
+            }
+        }
+    }
+
+    // The map replaces the board of the rules file, in any order
+    if let Some(board) = map {
+        settings.rules.board = board;
+    }
+
+    if settings.bots.is_empty() || settings.bots.len() > MAX_NUMBER_OF_PLAYERS {
+        return Err(format!(
+            "Between 1 and {} bots can play\n{}",
+            MAX_NUMBER_OF_PLAYERS, USAGE
+        ));
+    }
+    Ok(settings)
+}
+
+fn rules_file(path: &str) -> Result<GameRules, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|error| format!("Could not read the rules {}: {}", path, error))?;
+    GameRules::from_ron(&contents).map_err(|error| format!("Invalid rules {}: {}", path, error))
+}
+
+/// `brain` or `brain:difficulty` when it names a brain, the command or URL of an external bot otherwise
+fn bot_spec(
+    argument: &str,
+    registry: &BrainRegistry,
+    presets: &AiPresets,
+) -> Result<BotSpec, String> {
+    let (name, difficulty) = argument.split_once(':').unwrap_or((argument, "hard"));
+    if !registry.contains(name) {
+        return Ok(BotSpec::External(argument.to_string()));
+    }
+    let difficulty = Difficulty::ALL
+        .into_iter()
+        .find(|option| option.to_string().eq_ignore_ascii_case(difficulty))
+        .or_else(|| {
+            presets
+                .0
+                .contains_key(difficulty)
+                .then(|| Difficulty::Preset(difficulty.to_string()))
+        })
+        .ok_or_else(|| format!("Unknown difficulty {} for {}", difficulty, name))?;
+    Ok(BotSpec::Brain {
+        name: name.to_string(),
+        difficulty,
+    })
+}
+
+/// Plays one match, adding up how each player did into `results`
+/// Returns the index of the winner, if any, and the replay of the match
+fn play(settings: &Settings, seed: u64, results: &mut [Results]) -> (Option<usize>, Replay) {
+    let mut player_slots = PlayerSlots::default();
+    let mut brain_names = BrainNames::default();
+    let mut commands = BTreeMap::new();
+    for (player_number, bot) in (1..).zip(settings.bots.iter()) {
+        match bot {
+            BotSpec::Brain { name, difficulty } => {
+                player_slots.set(player_number, PlayerSlot::Bot(difficulty.clone()));
+                brain_names.0.insert(player_number, name.clone());
+            }
+            BotSpec::External(command) => {
+                player_slots.set(player_number, PlayerSlot::External);
+                commands.insert(player_number, command.clone());
+            }
+        }
+    }
+
+    let mut app = headless_app_with(settings.bots.len(), seed, |app| {
+        add_recording_systems(app);
+        app.add_plugins((AIPlugin, BotsPlugin))
+            .insert_resource(player_slots)
+            .insert_resource(brain_names)
+            .insert_resource(settings.presets.clone())
+            .insert_resource(settings.rules.clone());
+    });
+    let world = app.world_mut();
+    start_headless_bots(world, commands, settings.time_budget_ms);
+
+    let mut collisions = MessageCursor::<Collision>::default();
+    let winner = play_until_won(world, settings.max_ticks, |world| {
+        let hit = collisions
+            .read(world.resource::<Messages<Collision>>())
+            .map(|&Collision(entity)| entity)
+            .collect::<Vec<_>>();
+        for entity in hit {
+            if let Some(player) = player_index(world, entity) {
+                results[player].collisions += 1;
+            }
+        }
+    })
+    .and_then(|player_number| usize::from(player_number).checked_sub(1));
+    let replay = recorded(world);
+
+    let mut snakes = world.query::<&Snake>();
+    for snake in snakes.iter(world) {
+        if let Some(results) = usize::from(snake.player_number.0)
+            .checked_sub(1)
+            .and_then(|player| results.get_mut(player))
+        {
+            results.length += snake.segments.len();
+        }
+    }
+    if let Some(bots) = world.get_resource::<RunningBots>() {
+        for (player_number, timeouts) in bots.timeouts() {
+            if let Some(results) = usize::from(player_number)
+                .checked_sub(1)
+                .and_then(|player| results.get_mut(player))
+            {
+                results.timeouts += timeouts;
+            }
+        }
+    }
+    (winner, replay)
+}
+
+fn player_index(world: &World, entity: Entity) -> Option<usize> {
+    let snake = world.get::<Snake>(entity)?;
+    usize::from(snake.player_number.0).checked_sub(1)
+}
@@ -2,7 +2,8 @@ use bevy::prelude::*;
 
 #[derive(Resource, Debug, Default)]
 pub(crate) struct SceneAssets {
-    pub(crate) apple: Handle<Image>,
+    /// How the pieces of every snake skin are laid out, see [`SnakePiece`](crate::snake::SnakePiece)
+    pub(crate) snake_layout: Handle<TextureAtlasLayout>,
 }
 
 pub(crate) struct AssetLoaderPlugin;
@@ -14,8 +15,17 @@ impl Plugin for AssetLoaderPlugin {
     }
 }
 
-fn load_assets(mut scene_assets: ResMut<SceneAssets>, asset_server: Res<AssetServer>) {
+fn load_assets(
+    mut scene_assets: ResMut<SceneAssets>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
     *scene_assets = SceneAssets {
-        apple: asset_server.load("pumpkin.png"),
+        snake_layout: layouts.add(TextureAtlasLayout::from_grid(
+            UVec2::splat(16),
+            4,
+            1,
+            None,
+            None,
+        )),
     }
 }
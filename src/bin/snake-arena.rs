@@ -0,0 +1,11 @@
+//! Plays matches between bots without a window, see `snake_bevy::arena`
+
+fn main() {
+    match snake_bevy::arena::run(std::env::args()) {
+        Ok(results) => println!("{}", results),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
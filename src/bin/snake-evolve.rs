@@ -0,0 +1,11 @@
+//! Tunes the weights of the heuristic AI, see `snake_bevy::evolve`
+
+fn main() {
+    match snake_bevy::evolve::run(std::env::args()) {
+        Ok(report) => println!("{}", report),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,18 +1,19 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 
-use super::game_state::AppState;
+use super::game_state::InMatch;
+use super::rules::GameRules;
 
 pub(crate) struct BlinkPlugin;
 
-const BLINK_DURATION: f32 = 0.1;
-
 impl Plugin for BlinkPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(BlinkTimer(Timer::from_seconds(
-            BLINK_DURATION,
+            GameRules::default().blink_seconds,
             TimerMode::Repeating,
         )))
-        .add_systems(Update, (blink_tick,).run_if(in_state(AppState::InGame)));
+        .add_systems(Update, (blink_tick,).run_if(in_state(InMatch)));
     }
 }
 
@@ -26,7 +27,13 @@ fn blink_tick(
     time: Res<Time>,
     mut timer: ResMut<BlinkTimer>,
     mut blinking: Query<&mut Sprite, With<Blinking>>,
+    rules: Res<GameRules>,
 ) {
+    if rules.is_changed() {
+        timer
+            .0
+            .set_duration(Duration::from_secs_f32(rules.blink_seconds));
+    }
     if timer.0.tick(time.delta()).just_finished() {
         for mut sprite in blinking.iter_mut() {
             let current_alpha = sprite.color.alpha();
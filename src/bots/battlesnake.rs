@@ -0,0 +1,402 @@
+//! Bots written against the Battlesnake API, see https://docs.battlesnake.com/api
+//! The board wraps around, which is what Battlesnake calls the `wrapped` ruleset
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bevy::log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{BotState, Move, Point};
+use crate::{apple::NUMBER_OF_APPLES, direction::Direction};
+
+/// Snakes here don't starve, so their health is always full
+const FULL_HEALTH: u32 = 100;
+
+enum Request {
+    Start(String),
+    Move { tick: u32, body: String },
+    End(String),
+}
+
+/// A Battlesnake server, talked to from another thread so slow answers never block the game
+pub(super) struct Battlesnake {
+    requests: Sender<Request>,
+    game: Game,
+    started: bool,
+    /// Body of the last move request, which is also how the game ended
+    last_body: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Game {
+    id: String,
+    ruleset: Ruleset,
+    map: &'static str,
+    /// Milliseconds to answer a move
+    timeout: u64,
+    source: &'static str,
+}
+
+#[derive(Serialize)]
+struct Ruleset {
+    name: &'static str,
+    version: &'static str,
+    settings: RulesetSettings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RulesetSettings {
+    food_spawn_chance: u32,
+    minimum_food: usize,
+    hazard_damage_per_turn: u32,
+}
+
+#[derive(Serialize)]
+struct GameRequest<'a> {
+    game: &'a Game,
+    turn: u32,
+    board: Board,
+    you: Snake,
+}
+
+#[derive(Serialize)]
+struct Board {
+    width: i32,
+    height: i32,
+    food: Vec<Point>,
+    hazards: Vec<Point>,
+    snakes: Vec<Snake>,
+}
+
+#[derive(Serialize, Clone)]
+struct Snake {
+    id: String,
+    name: String,
+    health: u32,
+    /// Head first
+    body: Vec<Point>,
+    latency: String,
+    head: Point,
+    length: usize,
+    shout: String,
+    squad: String,
+}
+
+#[derive(Deserialize)]
+struct MoveResponse {
+    #[serde(rename = "move")]
+    direction: Move,
+}
+
+impl Battlesnake {
+    pub(super) fn connect(
+        url: &str,
+        player_number: u8,
+        game_id: String,
+        time_budget: Duration,
+    ) -> (Self, Receiver<(u32, Direction, Instant)>) {
+        let (requests, pending) = mpsc::channel();
+        let (answers, receiver) = mpsc::channel();
+        let url = url.trim_end_matches('/').to_string();
+        let agent = ureq::AgentBuilder::new().timeout(time_budget).build();
+        thread::spawn(move || {
+            while let Ok(mut request) = pending.recv() {
+                // A slow server answers the newest state instead of falling further behind
+                while let Request::Move { .. } = request {
+                    match pending.try_recv() {
+                        Ok(newer) => request = newer,
+                        Err(_) => break,
+                    }
+                }
+
+                match request {
+                    Request::Start(body) => {
+                        if let Err(error) = post(&agent, &url, "start", &body) {
+                            warn!(
+                                "The Battlesnake of player {} at {} didn't start: {}",
+                                player_number, url, error
+                            );
+                        }
+                    }
+                    Request::Move { tick, body } => {
+                        let response = post(&agent, &url, "move", &body).and_then(|response| {
+                            serde_json::from_str::<MoveResponse>(&response)
+                                .map_err(|error| error.to_string())
+                        });
+                        match response {
+                            Ok(response) => {
+                                let answer = (tick, response.direction.into(), Instant::now());
+                                if answers.send(answer).is_err() {
+                                    break;
+                                }
+                            }
+                            // Going straight is what happens on a timeout anyway
+                            Err(error) => debug!(
+                                "The Battlesnake of player {} didn't move on tick {}: {}",
+                                player_number, tick, error
+                            ),
+                        }
+                    }
+                    Request::End(body) => {
+                        let _ = post(&agent, &url, "end", &body);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let battlesnake = Self {
+            requests,
+            game: Game {
+                id: game_id,
+                ruleset: Ruleset {
+                    name: "wrapped",
+                    version: "v1.0.0",
+                    settings: RulesetSettings {
+                        // Eaten apples are replaced right away instead
+                        food_spawn_chance: 0,
+                        minimum_food: NUMBER_OF_APPLES,
+                        hazard_damage_per_turn: 0,
+                    },
+                },
+                map: "standard",
+                timeout: time_budget.as_millis() as u64,
+                source: "custom",
+            },
+            started: false,
+            last_body: None,
+        };
+        (battlesnake, receiver)
+    }
+
+    pub(super) fn send_move(&mut self, state: &BotState) -> io::Result<()> {
+        let body = self.request_body(state)?;
+        if !self.started {
+            self.started = true;
+            self.send(Request::Start(body.clone()))?;
+        }
+        self.last_body = Some(body.clone());
+        self.send(Request::Move {
+            tick: state.tick,
+            body,
+        })
+    }
+
+    fn send(&self, request: Request) -> io::Result<()> {
+        self.requests
+            .send(request)
+            .map_err(|_| io::Error::other("The connection to the Battlesnake stopped"))
+    }
+
+    fn request_body(&self, state: &BotState) -> io::Result<String> {
+        let snakes = state
+            .snakes
+            .iter()
+            .filter_map(|snake| {
+                let &head = snake.segments.first()?;
+                Some((
+                    snake.id,
+                    Snake {
+                        id: snake.id.to_string(),
+                        name: snake.name.clone(),
+                        health: FULL_HEALTH,
+                        body: snake.segments.clone(),
+                        latency: "0".to_string(),
+                        head,
+                        length: snake.segments.len(),
+                        shout: String::new(),
+                        squad: String::new(),
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        let you = snakes
+            .iter()
+            .find(|(id, _)| *id == state.you)
+            .map(|(_, snake)| snake.clone())
+            .ok_or_else(|| io::Error::other("The snake of the Battlesnake isn't on the board"))?;
+
+        let request = GameRequest {
+            game: &self.game,
+            turn: state.tick,
+            board: Board {
+                width: state.board.width,
+                height: state.board.height,
+                food: state.apples.clone(),
+                hazards: Vec::new(),
+                snakes: snakes.into_iter().map(|(_, snake)| snake).collect(),
+            },
+            you,
+        };
+        serde_json::to_string(&request).map_err(io::Error::other)
+    }
+}
+
+impl Drop for Battlesnake {
+    fn drop(&mut self) {
+        if let Some(body) = self.last_body.take() {
+            let _ = self.send(Request::End(body));
+        }
+    }
+}
+
+fn post(agent: &ureq::Agent, url: &str, endpoint: &str, body: &str) -> Result<String, String> {
+    agent
+        .post(&format!("{}/{}", url, endpoint))
+        .set("Content-Type", "application/json")
+        .send_string(body)
+        .map_err(|error| error.to_string())?
+        .into_string()
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::bots::{Board as StateBoard, BotSnake};
+
+    const TIME_BUDGET: Duration = Duration::from_millis(100);
+    /// Long enough for a slow test machine, a passing test never waits for it
+    const PATIENCE: Duration = Duration::from_secs(5);
+
+    fn point(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    fn state() -> BotState {
+        let snake = |id, segments| BotSnake {
+            id,
+            name: format!("Player {}", id),
+            direction: Move::Right,
+            segments,
+        };
+        BotState {
+            tick: 7,
+            you: 2,
+            board: StateBoard {
+                width: 11,
+                height: 11,
+            },
+            snakes: vec![
+                snake(1, vec![point(1, 1), point(0, 1)]),
+                snake(2, vec![point(5, 5), point(4, 5), point(3, 5)]),
+            ],
+            apples: vec![point(8, 2)],
+        }
+    }
+
+    /// A Battlesnake server that always moves left after `delay`
+    /// Returns its URL and every request it gets, as the endpoint and the JSON body
+    fn serve(delay: Duration) -> (String, Receiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let sender = sender.clone();
+                thread::spawn(move || answer(stream, delay, sender));
+            }
+        });
+        (url, requests)
+    }
+
+    fn answer(mut stream: TcpStream, delay: Duration, requests: Sender<(String, Value)>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        // Requests can share the connection
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let endpoint = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = requests.send((endpoint.clone(), serde_json::from_slice(&body).unwrap()));
+
+            let response = if endpoint == "/move" {
+                thread::sleep(delay);
+                r#"{"move": "left"}"#
+            } else {
+                ""
+            };
+            let written = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            if written.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn moves_are_asked_on_a_wrapped_board_for_the_right_snake() {
+        let (url, requests) = serve(Duration::ZERO);
+        let (mut battlesnake, answers) =
+            Battlesnake::connect(&url, 2, "game".to_string(), TIME_BUDGET);
+        battlesnake.send_move(&state()).unwrap();
+
+        let (endpoint, _) = requests.recv_timeout(PATIENCE).unwrap();
+        assert_eq!(endpoint, "/start");
+        let (endpoint, request) = requests.recv_timeout(PATIENCE).unwrap();
+        assert_eq!(endpoint, "/move");
+        assert_eq!(request["game"]["id"], "game");
+        assert_eq!(request["game"]["ruleset"]["name"], "wrapped");
+        assert_eq!(request["game"]["timeout"], 100);
+        assert_eq!(request["turn"], 7);
+        assert_eq!(request["board"]["width"], 11);
+        assert_eq!(request["board"]["food"], json!([{"x": 8, "y": 2}]));
+        assert_eq!(request["board"]["snakes"].as_array().unwrap().len(), 2);
+        assert_eq!(request["you"]["id"], "2");
+        assert_eq!(request["you"]["head"], json!({"x": 5, "y": 5}));
+        assert_eq!(request["you"]["length"], 3);
+        assert_eq!(
+            request["you"]["body"],
+            json!([{"x": 5, "y": 5}, {"x": 4, "y": 5}, {"x": 3, "y": 5}])
+        );
+
+        let (tick, direction, _) = answers.recv_timeout(PATIENCE).unwrap();
+        assert_eq!((tick, direction), (7, Direction::Left));
+    }
+
+    #[test]
+    fn slow_answers_are_dropped() {
+        let (url, requests) = serve(TIME_BUDGET * 5);
+        let (mut battlesnake, answers) =
+            Battlesnake::connect(&url, 2, "game".to_string(), TIME_BUDGET);
+        battlesnake.send_move(&state()).unwrap();
+
+        // The server got the move, it just didn't answer in time
+        let endpoints = [(); 2].map(|_| requests.recv_timeout(PATIENCE).unwrap().0);
+        assert_eq!(endpoints, ["/start", "/move"]);
+        assert!(answers.recv_timeout(TIME_BUDGET * 10).is_err());
+    }
+}
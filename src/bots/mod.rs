@@ -0,0 +1,516 @@
+//! Bots written in any language, running as child processes
+//! Every tick a bot gets the state of the game as one line of JSON on its stdin, see [`BotState`],
+//! and answers with one line on its stdout, like `{"tick": 12, "direction": "up"}`, for the tick it got
+//! Bots written against the Battlesnake API can play too, by giving their URL instead of a command
+//! An answer that doesn't arrive within the time budget keeps the snake going straight
+//! With a window the game never waits for them, the answers that arrived in time are taken on the next tick
+
+mod battlesnake;
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apple::Apple,
+    coordinate::Coordinate,
+    direction::Direction,
+    game_state::AppState,
+    main_menu::{load_player_slots, NumberOfPlayersSelected, PlayerSlot, PlayerSlots},
+    movement::{ProposeDirection, TickCount},
+    rng::Seed,
+    rules::GameRules,
+    schedule::tick_gate_open,
+    snake::{Id, Snake},
+    storage::Storage,
+};
+use battlesnake::Battlesnake;
+
+const BOTS_KEY: &str = "bots";
+/// Command line argument to assign a bot, like `--bot "2=python3 bot.py"`
+pub(crate) const BOT_ARGUMENT: &str = "--bot";
+/// Share of the tick bots can take to answer, the rest of it is left for the game
+const MAX_TIME_BUDGET_SHARE: f64 = 0.9;
+
+pub(crate) struct BotsPlugin;
+
+impl Plugin for BotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BotSettings>()
+            .add_systems(Startup, load_bot_settings.after(load_player_slots))
+            .add_systems(
+                Update,
+                start_bots
+                    .run_if(in_state(AppState::InGame).and(not(resource_exists::<RunningBots>))),
+            )
+            .add_systems(
+                FixedFirst,
+                collect_moves
+                    .run_if(in_state(AppState::InGame).and(resource_exists::<RunningBots>)),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                send_state.run_if(
+                    in_state(AppState::InGame)
+                        .and(resource_exists::<RunningBots>)
+                        .and(tick_gate_open),
+                ),
+            )
+            .add_systems(OnExit(AppState::InGame), stop_bots)
+            .add_systems(
+                EguiPrimaryContextPass,
+                bots_window.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// What runs the bot of each player, by player number
+#[derive(Resource, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct BotSettings {
+    /// A command, or the URL of a Battlesnake server
+    commands: BTreeMap<u8, String>,
+    /// How long a bot can take to answer, in milliseconds
+    time_budget_ms: u64,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            time_budget_ms: 50,
+        }
+    }
+}
+
+/// Same as [`Direction`], with the names bots use
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl From<Direction> for Move {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => Move::Up,
+            Direction::Down => Move::Down,
+            Direction::Left => Move::Left,
+            Direction::Right => Move::Right,
+        }
+    }
+}
+
+impl From<Move> for Direction {
+    fn from(bot_move: Move) -> Self {
+        match bot_move {
+            Move::Up => Direction::Up,
+            Move::Down => Direction::Down,
+            Move::Left => Direction::Left,
+            Move::Right => Direction::Right,
+        }
+    }
+}
+
+/// A cell of the board, from the bottom left corner, so `y` grows upwards
+#[derive(Serialize, Clone, Copy)]
+pub(crate) struct Point {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+}
+
+impl Point {
+    fn new(coordinate: &Coordinate, half_len: i32) -> Self {
+        Self {
+            x: coordinate.0.x as i32 + half_len,
+            y: coordinate.0.y as i32 + half_len,
+        }
+    }
+}
+
+/// What a bot gets every tick
+/// The board wraps around, leaving it on one side comes back from the other
+#[derive(Serialize)]
+struct BotState {
+    tick: u32,
+    /// Player number of the snake the bot moves
+    you: u8,
+    board: Board,
+    snakes: Vec<BotSnake>,
+    apples: Vec<Point>,
+}
+
+#[derive(Serialize)]
+struct Board {
+    width: i32,
+    height: i32,
+}
+
+#[derive(Serialize)]
+struct BotSnake {
+    id: u8,
+    name: String,
+    direction: Move,
+    /// Head first
+    segments: Vec<Point>,
+}
+
+#[derive(Deserialize)]
+struct BotAnswer {
+    tick: u32,
+    direction: Move,
+}
+
+struct Bot {
+    player_number: u8,
+    connection: Connection,
+    /// Directions the bot chose by tick and when they arrived, received on another thread
+    answers: Mutex<Receiver<(u32, Direction, Instant)>>,
+    /// Tick of the last state sent and when it was sent
+    waiting_for: Option<(u32, Instant)>,
+    /// Ticks the bot didn't answer in time
+    timeouts: u32,
+}
+
+enum Connection {
+    /// Reads states from its stdin and writes answers to its stdout
+    Process {
+        process: Child,
+        stdin: ChildStdin,
+    },
+    Battlesnake(Battlesnake),
+}
+
+impl Bot {
+    fn start(
+        player_number: u8,
+        command: &str,
+        game_id: String,
+        time_budget: Duration,
+    ) -> io::Result<Self> {
+        let command = command.trim();
+        if command.starts_with("http://") || command.starts_with("https://") {
+            let (battlesnake, answers) =
+                Battlesnake::connect(command, player_number, game_id, time_budget);
+            return Ok(Self {
+                player_number,
+                connection: Connection::Battlesnake(battlesnake),
+                answers: Mutex::new(answers),
+                waiting_for: None,
+                timeouts: 0,
+            });
+        }
+
+        let mut words = command.split_whitespace();
+        let program = words.next().unwrap_or_default();
+        let mut process = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        let (stdin, stdout) = match (process.stdin.take(), process.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => {
+                let _ = process.kill();
+                return Err(io::Error::other("Could not open the pipes of the bot"));
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let answer = match serde_json::from_str::<BotAnswer>(&line) {
+                    Ok(answer) => answer,
+                    Err(error) => {
+                        warn!(
+                            "Ignoring `{}` from the bot of player {}: {}",
+                            line, player_number, error
+                        );
+                        continue;
+                    }
+                };
+                if sender
+                    .send((answer.tick, answer.direction.into(), Instant::now()))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            player_number,
+            connection: Connection::Process { process, stdin },
+            answers: Mutex::new(receiver),
+            waiting_for: None,
+            timeouts: 0,
+        })
+    }
+
+    fn send(&mut self, state: &BotState) -> io::Result<()> {
+        match &mut self.connection {
+            Connection::Process { stdin, .. } => {
+                let line = serde_json::to_string(state).map_err(io::Error::other)?;
+                writeln!(stdin, "{}", line)?;
+                stdin.flush()
+            }
+            Connection::Battlesnake(battlesnake) => battlesnake.send_move(state),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Connection::Process { process, .. } = self {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct RunningBots(Vec<Bot>);
+
+/// Matches stepped by hand have no frames to keep drawing, so they wait for the answers instead
+#[derive(Resource)]
+struct WaitForBots;
+
+impl RunningBots {
+    /// Ticks each bot didn't answer in time, by player number
+    pub(crate) fn timeouts(&self) -> BTreeMap<u8, u32> {
+        self.0
+            .iter()
+            .map(|bot| (bot.player_number, bot.timeouts))
+            .collect()
+    }
+}
+
+/// Starts bots without a window, for matches that are stepped by hand, see `headless`
+pub(crate) fn start_headless_bots(
+    world: &mut World,
+    commands: BTreeMap<u8, String>,
+    time_budget_ms: u64,
+) {
+    world.insert_resource(BotSettings {
+        commands,
+        time_budget_ms,
+    });
+    world.insert_resource(WaitForBots);
+    let _ = world.run_system_cached(start_bots);
+}
+
+fn load_bot_settings(
+    mut settings: ResMut<BotSettings>,
+    mut player_slots: ResMut<PlayerSlots>,
+    storage: Res<Storage>,
+) {
+    if let Some(stored) = storage.load(BOTS_KEY) {
+        *settings = stored;
+    }
+
+    // Bots given on the command line replace the stored ones
+    let arguments = std::env::args().collect::<Vec<_>>();
+    for pair in arguments.windows(2) {
+        if pair[0] != BOT_ARGUMENT {
+            continue;
+        }
+        let assignment = pair[1]
+            .split_once('=')
+            .and_then(|(player_number, command)| Some((player_number.parse().ok()?, command)));
+        match assignment {
+            Some((player_number, command)) => {
+                settings.commands.insert(player_number, command.to_string());
+                player_slots.set(player_number, PlayerSlot::External);
+            }
+            None => warn!(
+                "Ignoring `{} {}`, it should look like `{} \"2=python3 bot.py\"`",
+                BOT_ARGUMENT, pair[1], BOT_ARGUMENT
+            ),
+        }
+    }
+}
+
+fn start_bots(
+    mut commands: Commands,
+    settings: Res<BotSettings>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    player_slots: Res<PlayerSlots>,
+    seed: Res<Seed>,
+) {
+    let time_budget = Duration::from_millis(settings.time_budget_ms);
+    let mut bots = Vec::new();
+    for (&player_number, command) in settings.commands.iter() {
+        if usize::from(player_number) > number_of_players.0
+            || player_slots.player(player_number) != PlayerSlot::External
+            || command.trim().is_empty()
+        {
+            continue;
+        }
+        match Bot::start(player_number, command, seed.0.to_string(), time_budget) {
+            Ok(bot) => bots.push(bot),
+            Err(error) => warn!(
+                "Could not start the bot of player {} with `{}`: {}",
+                player_number, command, error
+            ),
+        }
+    }
+    commands.insert_resource(RunningBots(bots));
+}
+
+fn stop_bots(mut commands: Commands) {
+    commands.remove_resource::<RunningBots>();
+}
+
+fn send_state(
+    mut bots: ResMut<RunningBots>,
+    tick_count: Res<TickCount>,
+    rules: Res<GameRules>,
+    snakes: Query<&Snake>,
+    coordinates: Query<&Coordinate>,
+    apples: Query<&Coordinate, With<Apple>>,
+) {
+    let half_len = rules.board.half_len;
+    let mut snakes = snakes
+        .iter()
+        .map(|snake| BotSnake {
+            id: snake.player_number.0,
+            name: snake.name.clone(),
+            direction: snake.direction.into(),
+            segments: snake
+                .segments
+                .iter()
+                .filter_map(|&segment| coordinates.get(segment).ok())
+                .map(|coordinate| Point::new(coordinate, half_len))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    snakes.sort_by_key(|snake| snake.id);
+    let mut state = BotState {
+        tick: tick_count.0,
+        you: 0,
+        board: Board {
+            width: rules.board.side(),
+            height: rules.board.side(),
+        },
+        snakes,
+        apples: apples
+            .iter()
+            .map(|coordinate| Point::new(coordinate, half_len))
+            .collect(),
+    };
+
+    bots.0.retain_mut(|bot| {
+        state.you = bot.player_number;
+        if let Err(error) = bot.send(&state) {
+            warn!("The bot of player {} stopped: {}", bot.player_number, error);
+            return false;
+        }
+        bot.waiting_for = Some((tick_count.0, Instant::now()));
+        true
+    });
+}
+
+/// Takes the answers to the last state that arrived within the time budget
+/// An answer still on its way by now is too late for its tick, since the tick is already running
+fn collect_moves(
+    mut bots: ResMut<RunningBots>,
+    settings: Res<BotSettings>,
+    wait_for_bots: Option<Res<WaitForBots>>,
+    mut propose_direction: MessageWriter<ProposeDirection>,
+) {
+    let time_budget = Duration::from_millis(settings.time_budget_ms);
+    for bot in bots.0.iter_mut() {
+        let Some((tick, sent_at)) = bot.waiting_for.take() else {
+            continue;
+        };
+        let Ok(answers) = bot.answers.get_mut() else {
+            continue;
+        };
+        let deadline = sent_at + time_budget;
+        loop {
+            let answer = if wait_for_bots.is_some() {
+                answers.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            } else {
+                answers.try_recv().map_err(|error| match error {
+                    TryRecvError::Empty => RecvTimeoutError::Timeout,
+                    TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                })
+            };
+            match answer {
+                Ok((answered, direction, arrived)) if answered == tick && arrived <= deadline => {
+                    propose_direction.write(ProposeDirection {
+                        id: Id(bot.player_number),
+                        direction,
+                    });
+                    break;
+                }
+                // Too late for its tick
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    bot.timeouts += 1;
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("The bot of player {} stopped", bot.player_number);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn bots_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<BotSettings>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    player_slots: Res<PlayerSlots>,
+    rules: Res<GameRules>,
+    storage: Res<Storage>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    let max_time_budget_ms = (rules.tick_seconds * MAX_TIME_BUDGET_SHARE * 1000.0) as u64;
+    egui::Window::new("Bots").show(ctx, |ui| {
+        ui.label("Command to run the bot of each external bot player, or the URL of a Battlesnake");
+        let mut changed = false;
+        egui::Grid::new("bots").show(ui, |ui| {
+            for player_number in (1..=number_of_players.0)
+                .filter_map(|n| u8::try_from(n).ok())
+                .filter(|&n| player_slots.player(n) == PlayerSlot::External)
+            {
+                ui.label(format!("Player {}", player_number));
+                let command = settings.commands.entry(player_number).or_default();
+                changed |= ui.text_edit_singleline(command).changed();
+                ui.end_row();
+            }
+        });
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut settings.time_budget_ms, 1..=max_time_budget_ms.max(1))
+                    .text("Time to answer (ms)"),
+            )
+            .changed();
+
+        if changed {
+            settings
+                .commands
+                .retain(|_, command| !command.trim().is_empty());
+            storage.save(BOTS_KEY, &*settings);
+        }
+    });
+}
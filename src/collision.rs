@@ -2,11 +2,12 @@ use bevy::prelude::*;
 
 use super::coordinate::Coordinate;
 
-use super::game_state::AppState;
+use super::schedule::TickSet;
 use super::snake::Snake;
 
 use super::blink::{BlinkPlugin, Blinking};
 use super::movement::Tick;
+use super::rules::GameRules;
 
 pub(crate) struct CollisionPlugin;
 
@@ -17,38 +18,31 @@ impl Plugin for CollisionPlugin {
             .add_message::<RemoveChunks>()
             .add_message::<SetInmortal>()
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
+                    update_inmortal_ticks,
                     collision_detection,
                     collision_handling,
                     remove_chunks,
                     set_inmortal,
-                    update_inmortal_ticks,
                 )
-                    .run_if(in_state(AppState::InGame)),
+                    .chain()
+                    .in_set(TickSet::Collision),
             );
     }
 }
 
-const INMORTAL_TICKS: u8 = 10;
-const PROPORTION_LOST_PER_HIT: f32 = 0.3;
-
 #[derive(Message)]
 /// Represents the snake entity that has hit its head against something
-struct Collision(Entity);
+pub(crate) struct Collision(pub(crate) Entity);
 
+// Every tick moves every snake, so this always runs instead of checking for changed coordinates
+// Change detection isn't part of a snapshot, so depending on it would break re-simulating ticks
 fn collision_detection(
     mut snake_query: Query<(Entity, &mut Snake)>,
     query: Query<&Coordinate>,
-    changed_coordinates: Query<Entity, Changed<Coordinate>>,
     mut collision: MessageWriter<Collision>,
 ) {
-    if changed_coordinates.iter().count() == 0 {
-        // This is an efficiency hack to just evaluate collisions when the state has changed
-        // TODO: find a better way to do this
-        return;
-    }
-
     let mut bodies_coordinates = std::collections::HashSet::new();
 
     for (_, snake) in snake_query.iter() {
@@ -76,7 +70,9 @@ fn collision_detection(
         .iter_mut()
         .filter(|(_, snake)| snake.inmortal_ticks == 0)
     {
-        let head_coordinate = get_head(&snake).unwrap();
+        let Some(head_coordinate) = get_head(&snake) else {
+            continue;
+        };
 
         if bodies_coordinates.contains(head_coordinate)
             || snake_heads_coordinates
@@ -101,18 +97,19 @@ fn collision_handling(
 }
 
 #[derive(Message)]
-struct RemoveChunks(Entity);
+pub(crate) struct RemoveChunks(pub(crate) Entity);
 
 fn remove_chunks(
     mut commands: Commands,
     mut event_reader: MessageReader<RemoveChunks>,
     mut query: Query<(Entity, &mut Snake)>,
+    rules: Res<GameRules>,
 ) {
     for RemoveChunks(entity) in event_reader.read() {
         if let Ok((_, mut snake)) = query.get_mut(*entity) {
             let chunks_to_remove = std::cmp::min(
                 snake.segments.len() - 1,
-                (snake.segments.len() as f32 * PROPORTION_LOST_PER_HIT).ceil() as usize,
+                (snake.segments.len() as f32 * rules.proportion_lost_per_hit).ceil() as usize,
             );
             for _ in 0..chunks_to_remove {
                 if let Some(entity) = snake.segments.pop_back() {
@@ -130,10 +127,11 @@ fn set_inmortal(
     mut commands: Commands,
     mut event_reader: MessageReader<SetInmortal>,
     mut query: Query<&mut Snake>,
+    rules: Res<GameRules>,
 ) {
     for &SetInmortal(entity) in event_reader.read() {
         if let Ok(mut snake) = query.get_mut(entity) {
-            snake.inmortal_ticks = INMORTAL_TICKS;
+            snake.inmortal_ticks = rules.inmortal_ticks;
             // TODO: how can we decouple the blinking from the inmortality?
             for &segment in snake.segments.iter() {
                 commands.entity(segment).insert(Blinking);
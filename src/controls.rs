@@ -0,0 +1,384 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use leafwing_input_manager::prelude::InputManagerPlugin;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    direction::Direction, game_state::AppState, gamepads::GamepadAssignments,
+    main_menu::NumberOfPlayersSelected, storage::Storage,
+};
+
+const CONTROLS_KEY: &str = "controls";
+const INPUT_BUFFERS_KEY: &str = "input_buffers";
+const MAX_INPUT_BUFFER_DEPTH: usize = 5;
+/// Already used to open and close the menu
+const RESERVED_KEYS: [KeyCode; 1] = [KeyCode::Escape];
+
+pub(crate) struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(InputManagerPlugin::<Direction>::default())
+            .init_resource::<KeyBindings>()
+            .init_resource::<InputBuffers>()
+            .init_resource::<Rebinding>()
+            .add_systems(Startup, (load_bindings, load_input_buffers))
+            .add_systems(Update, capture_binding.run_if(in_state(AppState::MainMenu)))
+            .add_systems(OnExit(AppState::MainMenu), cancel_rebinding)
+            .add_systems(
+                EguiPrimaryContextPass,
+                controls_window.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// Something that can be pressed to turn a snake
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Binding {
+    Key(KeyCode),
+    Button(GamepadButton),
+}
+
+impl Binding {
+    fn is_key(&self) -> bool {
+        matches!(self, Binding::Key(_))
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "{:?}", button),
+        }
+    }
+}
+
+/// At most one key and one gamepad button for each direction
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub(crate) struct PlayerBindings {
+    pub(crate) left: Vec<Binding>,
+    pub(crate) down: Vec<Binding>,
+    pub(crate) up: Vec<Binding>,
+    pub(crate) right: Vec<Binding>,
+}
+
+impl PlayerBindings {
+    fn new(keys: [KeyCode; 4], buttons: [Option<GamepadButton>; 4]) -> Self {
+        let mut bindings = Self::default();
+        for ((direction, key), button) in Direction::ALL.into_iter().zip(keys).zip(buttons) {
+            let direction_bindings = bindings.get_mut(direction);
+            direction_bindings.push(Binding::Key(key));
+            direction_bindings.extend(button.map(Binding::Button));
+        }
+        bindings
+    }
+
+    pub(crate) fn get(&self, direction: Direction) -> &Vec<Binding> {
+        match direction {
+            Direction::Left => &self.left,
+            Direction::Down => &self.down,
+            Direction::Up => &self.up,
+            Direction::Right => &self.right,
+        }
+    }
+
+    fn get_mut(&mut self, direction: Direction) -> &mut Vec<Binding> {
+        match direction {
+            Direction::Left => &mut self.left,
+            Direction::Down => &mut self.down,
+            Direction::Up => &mut self.up,
+            Direction::Right => &mut self.right,
+        }
+    }
+
+    /// Something like `ArrowLeft/DPadLeft ArrowDown ArrowUp ArrowRight`, in VIM ordering
+    pub(crate) fn describe(&self) -> String {
+        Direction::ALL
+            .iter()
+            .map(|&direction| {
+                let bindings = self.get(direction);
+                if bindings.is_empty() {
+                    return "-".to_string();
+                }
+                bindings
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Bindings of every player, the first one belongs to player 1
+/// Players without an entry have no bindings
+#[derive(Resource, Serialize, Deserialize, Clone)]
+pub(crate) struct KeyBindings(pub(crate) Vec<PlayerBindings>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use GamepadButton::*;
+        use KeyCode::*;
+
+        let d_pad = [
+            Some(DPadLeft),
+            Some(DPadDown),
+            Some(DPadUp),
+            Some(DPadRight),
+        ];
+        Self(vec![
+            PlayerBindings::new([ArrowLeft, ArrowDown, ArrowUp, ArrowRight], d_pad),
+            PlayerBindings::new([KeyA, KeyS, KeyW, KeyD], d_pad),
+            PlayerBindings::new([KeyJ, KeyK, KeyI, KeyL], d_pad),
+            PlayerBindings::new([Numpad4, Numpad5, Numpad8, Numpad6], d_pad),
+        ])
+    }
+}
+
+impl KeyBindings {
+    pub(crate) fn player(&self, player_number: u8) -> Option<&PlayerBindings> {
+        self.0.get(usize::from(player_number).checked_sub(1)?)
+    }
+
+    fn player_mut(&mut self, player_number: u8) -> &mut PlayerBindings {
+        let index = usize::from(player_number.max(1)) - 1;
+        if self.0.len() <= index {
+            self.0.resize_with(index + 1, default);
+        }
+        &mut self.0[index]
+    }
+
+    /// Player number and direction the binding is already used for
+    /// Every player has its own gamepad, so buttons only conflict with the player's other buttons
+    fn owner(&self, player_number: u8, binding: Binding) -> Option<(u8, Direction)> {
+        self.0
+            .iter()
+            .zip(1..)
+            .filter(|&(_, owner)| binding.is_key() || owner == player_number)
+            .find_map(|(bindings, player_number)| {
+                Direction::ALL
+                    .into_iter()
+                    .find(|&direction| bindings.get(direction).contains(&binding))
+                    .map(|direction| (player_number, direction))
+            })
+    }
+
+    /// Replaces the key or button of a direction, unless it's in use somewhere else
+    fn assign(
+        &mut self,
+        player_number: u8,
+        direction: Direction,
+        binding: Binding,
+    ) -> Result<(), (u8, Direction)> {
+        match self.owner(player_number, binding) {
+            Some(owner) if owner == (player_number, direction) => return Ok(()),
+            Some(owner) => return Err(owner),
+            None => {}
+        }
+        let bindings = self.player_mut(player_number).get_mut(direction);
+        bindings.retain(|other| other.is_key() != binding.is_key());
+        bindings.push(binding);
+        Ok(())
+    }
+}
+
+/// What happens to a direction pressed while the queue of the player is full
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub(crate) enum WhenFull {
+    #[default]
+    Drop,
+    OverwriteOldest,
+}
+
+/// How the directions pressed between ticks are queued
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct InputBuffer {
+    /// How many directions can wait for the next ticks
+    pub(crate) depth: usize,
+    pub(crate) when_full: WhenFull,
+    /// Queue the direction the snake is already going, for dash mechanics
+    pub(crate) allow_same_direction: bool,
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self {
+            depth: 2,
+            when_full: WhenFull::Drop,
+            allow_same_direction: false,
+        }
+    }
+}
+
+/// Input buffer of every player, the first one belongs to player 1
+/// Players without an entry use the default one
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct InputBuffers(pub(crate) Vec<InputBuffer>);
+
+impl InputBuffers {
+    pub(crate) fn player(&self, player_number: u8) -> InputBuffer {
+        usize::from(player_number)
+            .checked_sub(1)
+            .and_then(|index| self.0.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn set(&mut self, player_number: u8, input_buffer: InputBuffer) {
+        let index = usize::from(player_number.max(1)) - 1;
+        if self.0.len() <= index {
+            self.0.resize_with(index + 1, default);
+        }
+        self.0[index] = input_buffer;
+    }
+}
+
+/// The controls screen is waiting for the player to press something
+#[derive(Resource, Default)]
+struct Rebinding {
+    waiting_for: Option<(u8, Direction)>,
+    /// Explanation of why the last rebind was rejected
+    error: Option<String>,
+}
+
+fn load_bindings(mut key_bindings: ResMut<KeyBindings>, storage: Res<Storage>) {
+    if let Some(stored) = storage.load(CONTROLS_KEY) {
+        *key_bindings = stored;
+    }
+}
+
+/// Also called when a replay made with other settings stops
+pub(crate) fn load_input_buffers(mut input_buffers: ResMut<InputBuffers>, storage: Res<Storage>) {
+    *input_buffers = storage.load(INPUT_BUFFERS_KEY).unwrap_or_default();
+}
+
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    assignments: Res<GamepadAssignments>,
+    storage: Res<Storage>,
+) {
+    let Some((player_number, direction)) = rebinding.waiting_for else {
+        return;
+    };
+
+    let key = keyboard_input
+        .get_just_pressed()
+        .find(|key| !RESERVED_KEYS.contains(key))
+        .map(|&key| Binding::Key(key));
+    // Only the player's own gamepad, if it has one
+    let assigned = assignments.gamepad(player_number);
+    let button = || {
+        gamepads
+            .iter()
+            .filter(|(entity, _)| assigned.is_none_or(|assigned| assigned == *entity))
+            .find_map(|(_, gamepad)| gamepad.get_just_pressed().next())
+            .map(|&button| Binding::Button(button))
+    };
+    let Some(binding) = key.or_else(button) else {
+        return;
+    };
+
+    rebinding.waiting_for = None;
+    rebinding.error = match key_bindings.assign(player_number, direction, binding) {
+        Ok(()) => {
+            storage.save(CONTROLS_KEY, &*key_bindings);
+            None
+        }
+        Err((owner, owner_direction)) => Some(format!(
+            "`{}` is already used by player {} {:?}",
+            binding, owner, owner_direction
+        )),
+    };
+}
+
+fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+    *rebinding = Rebinding::default();
+}
+
+fn controls_window(
+    mut contexts: EguiContexts,
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut input_buffers: ResMut<InputBuffers>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    storage: Res<Storage>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Controls").show(ctx, |ui| {
+        for player_number in (1..=number_of_players.0).filter_map(|n| u8::try_from(n).ok()) {
+            ui.collapsing(format!("Player {}", player_number), |ui| {
+                egui::Grid::new(("controls", player_number))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for direction in Direction::ALL {
+                            ui.label(format!("{:?}", direction));
+                            ui.label(
+                                key_bindings
+                                    .player(player_number)
+                                    .map(|bindings| {
+                                        bindings
+                                            .get(direction)
+                                            .iter()
+                                            .map(ToString::to_string)
+                                            .collect::<Vec<_>>()
+                                            .join(", ")
+                                    })
+                                    .unwrap_or_default(),
+                            );
+                            if rebinding.waiting_for == Some((player_number, direction)) {
+                                ui.label("Press a key or button...");
+                            } else if ui.button("Rebind").clicked() {
+                                rebinding.waiting_for = Some((player_number, direction));
+                                rebinding.error = None;
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                let mut input_buffer = input_buffers.player(player_number);
+                ui.add(
+                    egui::Slider::new(&mut input_buffer.depth, 1..=MAX_INPUT_BUFFER_DEPTH)
+                        .text("Buffered directions"),
+                );
+                ui.horizontal(|ui| {
+                    ui.label("When full");
+                    ui.radio_value(&mut input_buffer.when_full, WhenFull::Drop, "Drop new");
+                    ui.radio_value(
+                        &mut input_buffer.when_full,
+                        WhenFull::OverwriteOldest,
+                        "Overwrite oldest",
+                    );
+                });
+                ui.checkbox(
+                    &mut input_buffer.allow_same_direction,
+                    "Allow pressing the current direction",
+                );
+                if input_buffer != input_buffers.player(player_number) {
+                    input_buffers.set(player_number, input_buffer);
+                    storage.save(INPUT_BUFFERS_KEY, &*input_buffers);
+                }
+            });
+        }
+
+        if rebinding.waiting_for.is_some() && ui.button("Cancel").clicked() {
+            rebinding.waiting_for = None;
+        }
+        if let Some(error) = &rebinding.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+        if ui.button("Reset to defaults").clicked() {
+            *key_bindings = KeyBindings::default();
+            *input_buffers = InputBuffers::default();
+            *rebinding = Rebinding::default();
+            storage.save(CONTROLS_KEY, &*key_bindings);
+            storage.save(INPUT_BUFFERS_KEY, &*input_buffers);
+        }
+    });
+}
@@ -1,9 +1,12 @@
 use bevy::prelude::{Reflect, Vec2};
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 // TODO: can we decouple the Actionlike trait?
 // Problem: Actionlike is only derivable in enums, so wrapping it in a `struct Wrapper(Direction)` won't work with #[derive]
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+#[derive(
+    Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Serialize, Deserialize,
+)]
 pub enum Direction {
     Down,
     Left,
@@ -11,6 +14,16 @@ pub enum Direction {
     Up,
 }
 
+impl Direction {
+    /// VIM ordering
+    pub(crate) const ALL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Down,
+        Direction::Up,
+        Direction::Right,
+    ];
+}
+
 impl Into<Vec2> for Direction {
     fn into(self) -> Vec2 {
         let x = match self {
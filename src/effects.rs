@@ -0,0 +1,344 @@
+//! Particles and camera shake when something happens in a match
+//! Only for show, they use their own randomness and never touch the game state
+
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use rand::Rng;
+
+use crate::{
+    apple::AppleEaten,
+    collision::{Collision, RemoveChunks},
+    game_state::{AppState, InMatch},
+    interpolation::SmoothMovement,
+    schedule::InGameSet,
+    snake::{update_local_coordinates_to_world_transforms, MyColor, Snake},
+    storage::Storage,
+    win::Won,
+};
+
+const REDUCE_MOTION_KEY: &str = "reduce_motion";
+/// Above the snakes and apples
+const PARTICLE_DEPTH: f32 = 2.0;
+const SPARK_SIZE: f32 = 0.15;
+/// Text is laid out in pixels, this makes it about a cell tall
+const TEXT_SCALE: f32 = 0.02;
+/// Speed lost per second, as a proportion
+const DRAG: f32 = 2.0;
+/// Shake lost per second
+const SHAKE_DECAY: f32 = 2.0;
+/// Cells the camera moves at full shake
+const MAX_SHAKE_OFFSET: f32 = 0.4;
+
+pub(crate) struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReduceMotion>()
+            .init_resource::<Shake>()
+            .init_resource::<DrawnSegments>()
+            .add_systems(Startup, load_reduce_motion)
+            .add_systems(
+                Update,
+                (
+                    apple_burst,
+                    collision_sparks,
+                    scatter_lost_segments,
+                    win_confetti,
+                )
+                    .in_set(InGameSet::SpawnDespawnEntities),
+            )
+            .add_systems(Update, (update_particles, shake_camera))
+            .add_systems(
+                Update,
+                remember_drawn_segments
+                    .after(update_local_coordinates_to_world_transforms)
+                    .in_set(InGameSet::Last)
+                    .run_if(in_state(InMatch)),
+            )
+            .add_systems(
+                EguiPrimaryContextPass,
+                motion_selection.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// Particles fade where they appear and the camera stays still
+#[derive(Resource, Default)]
+struct ReduceMotion(bool);
+
+impl ReduceMotion {
+    /// How much of the usual movement there is
+    fn motion(&self) -> f32 {
+        if self.0 {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// From 0 to 1, how strongly the camera shakes
+#[derive(Resource, Default)]
+struct Shake(f32);
+
+impl Shake {
+    fn add(&mut self, amount: f32, reduce_motion: &ReduceMotion) {
+        if !reduce_motion.0 {
+            self.0 = (self.0 + amount).min(1.0);
+        }
+    }
+}
+
+/// How the segments of each snake looked last frame, the ones lost on a hit are already gone when it's noticed
+#[derive(Resource, Default)]
+struct DrawnSegments(HashMap<Entity, Vec<(Sprite, Transform)>>);
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    /// Radians per second
+    spin: f32,
+    /// Fades out as it runs
+    lifetime: Timer,
+}
+
+fn load_reduce_motion(mut reduce_motion: ResMut<ReduceMotion>, storage: Res<Storage>) {
+    reduce_motion.0 = storage.load(REDUCE_MOTION_KEY).unwrap_or_default();
+}
+
+fn head_position(snake: &Snake, transforms: &Query<&Transform>) -> Option<Vec2> {
+    let &head = snake.segments.front()?;
+    Some(transforms.get(head).ok()?.translation.truncate())
+}
+
+fn random_velocity(rng: &mut impl Rng, min_speed: f32, max_speed: f32) -> Vec2 {
+    Vec2::from_angle(rng.gen_range(0.0..TAU)) * rng.gen_range(min_speed..max_speed)
+}
+
+fn spawn_sparks(
+    commands: &mut Commands,
+    at: Vec2,
+    color: Color,
+    count: usize,
+    speed: f32,
+    reduce_motion: &ReduceMotion,
+) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..count {
+        let offset = if reduce_motion.0 {
+            // Spread around instead of flying out
+            random_velocity(&mut rng, 0.0, 0.5)
+        } else {
+            Vec2::ZERO
+        };
+        commands.spawn((
+            Sprite::from_color(color, Vec2::splat(SPARK_SIZE)),
+            Transform::from_translation((at + offset).extend(PARTICLE_DEPTH)),
+            Particle {
+                velocity: random_velocity(&mut rng, speed * 0.3, speed) * reduce_motion.motion(),
+                spin: rng.gen_range(-TAU..TAU) * reduce_motion.motion(),
+                lifetime: Timer::from_seconds(rng.gen_range(0.3..0.6), TimerMode::Once),
+            },
+        ));
+    }
+}
+
+fn apple_burst(
+    mut commands: Commands,
+    mut apple_eaten: MessageReader<AppleEaten>,
+    snakes: Query<(&Snake, &MyColor)>,
+    transforms: Query<&Transform>,
+    reduce_motion: Res<ReduceMotion>,
+) {
+    for AppleEaten(entity) in apple_eaten.read() {
+        let Ok((snake, color)) = snakes.get(*entity) else {
+            continue;
+        };
+        let Some(at) = head_position(snake, &transforms) else {
+            continue;
+        };
+        spawn_sparks(&mut commands, at, color.0, 12, 4.0, &reduce_motion);
+        commands.spawn((
+            Text2d::new("+1"),
+            TextFont {
+                font_size: 40.0,
+                ..default()
+            },
+            TextColor(color.0),
+            Transform::from_translation((at + Vec2::Y * 0.5).extend(PARTICLE_DEPTH))
+                .with_scale(Vec3::splat(TEXT_SCALE)),
+            Particle {
+                velocity: Vec2::Y * 1.5 * reduce_motion.motion(),
+                spin: 0.0,
+                lifetime: Timer::from_seconds(0.8, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+fn collision_sparks(
+    mut commands: Commands,
+    mut collision: MessageReader<Collision>,
+    snakes: Query<&Snake>,
+    transforms: Query<&Transform>,
+    reduce_motion: Res<ReduceMotion>,
+    mut shake: ResMut<Shake>,
+) {
+    for Collision(entity) in collision.read() {
+        let Ok(snake) = snakes.get(*entity) else {
+            continue;
+        };
+        let Some(at) = head_position(snake, &transforms) else {
+            continue;
+        };
+        spawn_sparks(&mut commands, at, Color::WHITE, 16, 6.0, &reduce_motion);
+        shake.add(0.4, &reduce_motion);
+    }
+}
+
+/// The segments lost on a hit fly off instead of disappearing
+fn scatter_lost_segments(
+    mut commands: Commands,
+    mut remove_chunks: MessageReader<RemoveChunks>,
+    snakes: Query<&Snake>,
+    drawn_segments: Res<DrawnSegments>,
+    reduce_motion: Res<ReduceMotion>,
+) {
+    let mut rng = rand::thread_rng();
+    for RemoveChunks(entity) in remove_chunks.read() {
+        let (Ok(snake), Some(drawn)) = (snakes.get(*entity), drawn_segments.0.get(entity)) else {
+            continue;
+        };
+        for (sprite, transform) in drawn.iter().skip(snake.segments.len()) {
+            let mut sprite = sprite.clone();
+            // It might have been caught mid blink
+            sprite.color.set_alpha(1.0);
+            let mut transform = *transform;
+            transform.translation.z = PARTICLE_DEPTH;
+            commands.spawn((
+                sprite,
+                transform,
+                Particle {
+                    velocity: random_velocity(&mut rng, 1.0, 4.0) * reduce_motion.motion(),
+                    spin: rng.gen_range(-TAU..TAU) * reduce_motion.motion(),
+                    lifetime: Timer::from_seconds(rng.gen_range(0.5..0.9), TimerMode::Once),
+                },
+            ));
+        }
+    }
+}
+
+fn win_confetti(
+    mut commands: Commands,
+    mut won: MessageReader<Won>,
+    snakes: Query<(&Snake, &MyColor)>,
+    transforms: Query<&Transform>,
+    reduce_motion: Res<ReduceMotion>,
+    mut shake: ResMut<Shake>,
+) {
+    for Won(name) in won.read() {
+        let Some((snake, color)) = snakes.iter().find(|(snake, _)| &snake.name == name) else {
+            continue;
+        };
+        for &segment in snake.segments.iter() {
+            let Ok(transform) = transforms.get(segment) else {
+                continue;
+            };
+            let at = transform.translation.truncate();
+            spawn_sparks(&mut commands, at, color.0, 6, 5.0, &reduce_motion);
+            spawn_sparks(&mut commands, at, Color::WHITE, 2, 5.0, &reduce_motion);
+        }
+        shake.add(0.8, &reduce_motion);
+    }
+}
+
+fn remember_drawn_segments(
+    snakes: Query<(Entity, &Snake)>,
+    segments: Query<(&Sprite, &Transform)>,
+    mut drawn_segments: ResMut<DrawnSegments>,
+) {
+    drawn_segments.0.clear();
+    for (entity, snake) in snakes.iter() {
+        let drawn = snake
+            .segments
+            .iter()
+            .filter_map(|&segment| segments.get(segment).ok())
+            .map(|(sprite, transform)| (sprite.clone(), *transform))
+            .collect();
+        drawn_segments.0.insert(entity, drawn);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_particles(
+    mut commands: Commands,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        Option<&mut Sprite>,
+        Option<&mut TextColor>,
+    )>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut particle, mut transform, sprite, text_color) in particles.iter_mut() {
+        if particle.lifetime.tick(time.delta()).is_finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += (particle.velocity * delta).extend(0.0);
+        transform.rotate_z(particle.spin * delta);
+        particle.velocity *= (1.0 - DRAG * delta).max(0.0);
+
+        let alpha = particle.lifetime.fraction_remaining();
+        if let Some(mut sprite) = sprite {
+            sprite.color.set_alpha(alpha);
+        }
+        if let Some(mut text_color) = text_color {
+            text_color.0.set_alpha(alpha);
+        }
+    }
+}
+
+fn shake_camera(
+    mut shake: ResMut<Shake>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+    time: Res<Time>,
+) {
+    if shake.0 <= 0.0 {
+        return;
+    }
+    shake.0 = (shake.0 - SHAKE_DECAY * time.delta_secs()).max(0.0);
+    // Squared so small shakes are subtle
+    let offset = if shake.0 > 0.0 {
+        random_velocity(&mut rand::thread_rng(), 0.0, 1.0) * shake.0 * shake.0 * MAX_SHAKE_OFFSET
+    } else {
+        Vec2::ZERO
+    };
+    for mut transform in cameras.iter_mut() {
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+    }
+}
+
+/// Every setting about how things move on screen, in one place
+fn motion_selection(
+    mut contexts: EguiContexts,
+    mut smooth_movement: ResMut<SmoothMovement>,
+    mut reduce_motion: ResMut<ReduceMotion>,
+    storage: Res<Storage>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Motion").show(ctx, |ui| {
+        smooth_movement.checkbox(ui, &storage);
+        if ui.checkbox(&mut reduce_motion.0, "Reduce motion").changed() {
+            storage.save(REDUCE_MOTION_KEY, &reduce_motion.0);
+        }
+    });
+}
@@ -0,0 +1,320 @@
+//! Tunes the weights of the `heuristic` brain by evolution, playing matches without a window against a fixed opponent
+//! Run with `snake-evolve [--generations G] [--population P] [--matches M] [--seed S] [--max-ticks T] [--opponent BRAIN] [--name NAME]`
+//! The best weights are saved as an AI preset called NAME, which can be picked as the difficulty of a bot
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    ai::{
+        AIPlugin, AiPresets, BrainNames, BrainRegistry, Difficulty, DifficultySettings, Weights,
+        PRESETS_DIRECTORY,
+    },
+    headless::{headless_app_with, play_until_won},
+    launch::number,
+    main_menu::{PlayerSlot, PlayerSlots},
+    rules::GameRules,
+    snake::Snake,
+    storage::Storage,
+};
+
+const USAGE: &str = "Usage: snake-evolve [--generations G] [--population P] [--matches M] [--seed S] [--max-ticks T] [--opponent BRAIN] [--name NAME]";
+
+/// Name of the preset the candidates play with while they are evaluated
+const CANDIDATE: &str = "candidate";
+const CANDIDATE_BRAIN: &str = "heuristic";
+/// Part of each generation that goes on to the next one unchanged
+const ELITE_PROPORTION: f32 = 0.25;
+/// Probability of each weight changing in a child
+const MUTATION_RATE: f64 = 0.3;
+/// Largest change of a weight, relative to its size
+const MUTATION_SCALE: f32 = 0.5;
+
+struct Settings {
+    generations: u32,
+    population: usize,
+    /// Per candidate and generation, every candidate of a generation plays the same seeds
+    matches: u32,
+    seed: u64,
+    /// A match nobody won by then is a draw
+    max_ticks: u32,
+    opponent: String,
+    name: String,
+}
+
+/// Evolves the weights and saves the best ones as a preset
+pub fn run(arguments: impl Iterator<Item = String>) -> Result<String, String> {
+    let settings = parse(arguments)?;
+    let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+
+    // Starting around the hand picked weights
+    let mut population = vec![Weights::default()];
+    while population.len() < settings.population {
+        population.push(mutate(Weights::default(), &mut rng));
+    }
+
+    let mut best = (Weights::default(), f32::MIN);
+    for generation in 0..settings.generations {
+        let first_seed = settings
+            .seed
+            .wrapping_add(u64::from(generation * settings.matches));
+        let mut ranked = population
+            .iter()
+            .copied()
+            .zip(evaluate(&population, first_seed, &settings))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        best = ranked[0];
+        let mean = ranked.iter().map(|(_, fitness)| fitness).sum::<f32>() / ranked.len() as f32;
+        println!(
+            "Generation {}: best {:.3}, mean {:.3}",
+            generation + 1,
+            best.1,
+            mean
+        );
+
+        let elite = ((ranked.len() as f32 * ELITE_PROPORTION).ceil() as usize).max(1);
+        let parents = ranked[..elite]
+            .iter()
+            .map(|&(weights, _)| weights)
+            .collect::<Vec<_>>();
+        population = parents.clone();
+        while population.len() < settings.population {
+            let (Some(&a), Some(&b)) = (parents.choose(&mut rng), parents.choose(&mut rng)) else {
+                break;
+            };
+            population.push(mutate(crossover(a, b, &mut rng), &mut rng));
+        }
+    }
+
+    Storage::platform_default().save(
+        &format!("{}/{}", PRESETS_DIRECTORY, settings.name),
+        &candidate_settings(best.0),
+    );
+    let weights =
+        ron::ser::to_string_pretty(&best.0, default()).map_err(|error| error.to_string())?;
+    Ok(format!(
+        "Saved the AI preset {} with a fitness of {:.3}, pick it as the difficulty of a bot\n{}",
+        settings.name, best.1, weights
+    ))
+}
+
+fn parse(mut arguments: impl Iterator<Item = String>) -> Result<Settings, String> {
+    // Only to know which names are brains
+    let mut brains = App::new();
+    brains.add_plugins(AIPlugin);
+    let registry = brains.world().resource::<BrainRegistry>();
+
+    let mut settings = Settings {
+        generations: 20,
+        population: 16,
+        matches: 4,
+        seed: rand::random(),
+        max_ticks: 1000,
+        opponent: "pathfinding".to_string(),
+        name: "evolved".to_string(),
+    };
+    // The name of the program
+    arguments.next();
+    while let Some(argument) = arguments.next() {
+        let value = arguments
+            .next()
+            .ok_or_else(|| format!("Missing the value of {}\n{}", argument, USAGE))?;
+        match argument.as_str() {
+            "--generations" => settings.generations = number(&value)?,
+            "--population" => settings.population = number(&value)?,
+            "--matches" => settings.matches = number(&value)?,
+            "--seed" => settings.seed = number(&value)?,
+            "--max-ticks" => settings.max_ticks = number(&value)?,
+            "--opponent" if registry.contains(&value) => settings.opponent = value,
+            "--opponent" => return Err(format!("Unknown brain {}\n{}", value, USAGE)),
+            "--name" => settings.name = value,
+            _ => return Err(format!("Unknown argument {}\n{}", argument, USAGE)),
+        }
+    }
+    if settings.population < 2 || settings.matches == 0 {
+        return Err(format!(
+            "At least 2 candidates and 1 match are needed\n{}",
+            USAGE
+        ));
+    }
+    Ok(settings)
+}
+
+fn mutate(weights: Weights, rng: &mut impl Rng) -> Weights {
+    Weights::from_array(weights.to_array().map(|weight| {
+        if rng.gen_bool(MUTATION_RATE) {
+            weight + weight.abs().max(1.0) * MUTATION_SCALE * rng.gen_range(-1.0..=1.0)
+        } else {
+            weight
+        }
+    }))
+}
+
+/// Each weight from one of the parents
+fn crossover(a: Weights, b: Weights, rng: &mut impl Rng) -> Weights {
+    let (a, b) = (a.to_array(), b.to_array());
+    Weights::from_array(std::array::from_fn(|index| {
+        if rng.gen_bool(0.5) {
+            a[index]
+        } else {
+            b[index]
+        }
+    }))
+}
+
+fn candidate_settings(weights: Weights) -> DifficultySettings {
+    DifficultySettings {
+        brain: CANDIDATE_BRAIN.to_string(),
+        weights,
+        ..Difficulty::Hard.settings(&AiPresets::default())
+    }
+}
+
+/// Fitness of every candidate, with matches on as many threads as there are cores
+fn evaluate(population: &[Weights], first_seed: u64, settings: &Settings) -> Vec<f32> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = population.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles = population
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&weights| fitness(weights, first_seed, settings))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// From 0 to about 1.5, winning counts the most and growing the snake counts a bit
+fn fitness(weights: Weights, first_seed: u64, settings: &Settings) -> f32 {
+    let total = (0..settings.matches)
+        .map(|index| {
+            // Both starting spots, in case one of them is better
+            let player_number = 1 + (index % 2) as u8;
+            let seed = first_seed.wrapping_add(u64::from(index));
+            let (won, length) = play(weights, player_number, seed, settings);
+            f32::from(u8::from(won))
+                + 0.5 * (length as f32 / GameRules::default().length_to_win as f32).min(1.0)
+        })
+        .sum::<f32>();
+    total / settings.matches as f32
+}
+
+/// Plays a match of the candidate as `player_number` against the opponent
+/// Returns whether the candidate won and how long it ended up
+fn play(weights: Weights, player_number: u8, seed: u64, settings: &Settings) -> (bool, usize) {
+    let opponent_number = 3 - player_number;
+    let mut player_slots = PlayerSlots::default();
+    player_slots.set(
+        player_number,
+        PlayerSlot::Bot(Difficulty::Preset(CANDIDATE.to_string())),
+    );
+    player_slots.set(opponent_number, PlayerSlot::Bot(Difficulty::Hard));
+    // Named for both, so `AI_BRAIN` can't replace them
+    let brain_names = BrainNames(BTreeMap::from([
+        (player_number, CANDIDATE_BRAIN.to_string()),
+        (opponent_number, settings.opponent.clone()),
+    ]));
+    let presets = AiPresets(BTreeMap::from([(
+        CANDIDATE.to_string(),
+        candidate_settings(weights),
+    )]));
+
+    let mut app = headless_app_with(2, seed, |app| {
+        app.add_plugins(AIPlugin)
+            .insert_resource(player_slots)
+            .insert_resource(brain_names)
+            .insert_resource(presets);
+    });
+    let world = app.world_mut();
+    let winner = play_until_won(world, settings.max_ticks, |_| {});
+
+    let mut snakes = world.query::<&Snake>();
+    let Some(candidate) = snakes
+        .iter(world)
+        .find(|snake| snake.player_number.0 == player_number)
+    else {
+        return (false, 0);
+    };
+    (winner == Some(player_number), candidate.segments.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            generations: 1,
+            population: 2,
+            matches: 2,
+            seed: 0,
+            max_ticks: 200,
+            opponent: "pathfinding".to_string(),
+            name: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn mutations_stay_within_the_scale() {
+        let weights = Weights::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mutated = mutate(weights, &mut rng);
+            for (before, after) in weights.to_array().into_iter().zip(mutated.to_array()) {
+                assert!((after - before).abs() <= before.abs().max(1.0) * MUTATION_SCALE);
+            }
+        }
+    }
+
+    #[test]
+    fn crossover_takes_each_weight_from_a_parent() {
+        let a = Weights::default();
+        let b = Weights::from_array(a.to_array().map(|weight| weight * 2.0));
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let child = crossover(a, b, &mut rng).to_array();
+            for (index, weight) in child.into_iter().enumerate() {
+                assert!(weight == a.to_array()[index] || weight == b.to_array()[index]);
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_fitness() {
+        let settings = settings();
+        let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+        let weights = mutate(Weights::default(), &mut rng);
+        assert_eq!(
+            fitness(weights, settings.seed, &settings),
+            fitness(weights, settings.seed, &settings)
+        );
+    }
+
+    #[test]
+    fn unknown_opponents_are_rejected() {
+        let arguments = |opponent: &'static str| {
+            ["snake-evolve", "--opponent", opponent]
+                .into_iter()
+                .map(String::from)
+        };
+        assert!(parse(arguments("mcts")).is_ok());
+        assert!(parse(arguments("nobody")).is_err());
+    }
+}
@@ -5,6 +5,22 @@ pub(crate) enum AppState {
     MainMenu,
     #[default]
     InGame,
+    Replay,
+}
+
+/// Exists while snakes are on the board, either being played or replayed
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct InMatch;
+
+impl ComputedStates for InMatch {
+    type SourceStates = AppState;
+
+    fn compute(app_state: AppState) -> Option<Self> {
+        match app_state {
+            AppState::InGame | AppState::Replay => Some(InMatch),
+            AppState::MainMenu => None,
+        }
+    }
 }
 
 pub(crate) struct GameStatePlugin;
@@ -12,6 +28,7 @@ pub(crate) struct GameStatePlugin;
 impl Plugin for GameStatePlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<AppState>()
+            .add_computed_state::<InMatch>()
             .add_systems(Update, game_state_transition);
     }
 }
@@ -24,7 +41,7 @@ fn game_state_transition(
     if keyboard_input.just_pressed(KeyCode::Escape) {
         app_state_next_state.set(match app_state.get() {
             AppState::MainMenu => AppState::InGame,
-            AppState::InGame => AppState::MainMenu,
+            AppState::InGame | AppState::Replay => AppState::MainMenu,
         })
     }
 }
@@ -0,0 +1,260 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use bevy::{input::gamepad::GamepadConnectionEvent, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    direction::Direction,
+    game_state::AppState,
+    main_menu::{NumberOfPlayersSelected, PlayerSlot, PlayerSlots},
+    snake::Snake,
+};
+
+const JOIN_BUTTON: GamepadButton = GamepadButton::South;
+/// How far the stick has to be pushed before it turns the snake
+const STICK_DEADZONE: f32 = 0.5;
+
+pub(crate) struct GamepadsPlugin;
+
+impl Plugin for GamepadsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadAssignments>()
+            .add_systems(
+                Update,
+                (
+                    (handle_connections, join).chain(),
+                    rebuild_input_maps.run_if(resource_changed::<GamepadAssignments>),
+                ),
+            )
+            .add_systems(
+                EguiPrimaryContextPass,
+                gamepads_window.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// Which gamepad drives each player, by player number
+#[derive(Resource, Default)]
+pub(crate) struct GamepadAssignments {
+    players: BTreeMap<u8, Entity>,
+    /// Gamepads that were unplugged, so they get their player back when plugged in again
+    disconnected: BTreeMap<u8, Entity>,
+}
+
+impl GamepadAssignments {
+    pub(crate) fn gamepad(&self, player_number: u8) -> Option<Entity> {
+        self.players.get(&player_number).copied()
+    }
+
+    fn player(&self, gamepad: Entity) -> Option<u8> {
+        self.players
+            .iter()
+            .find(|(_, &assigned)| assigned == gamepad)
+            .map(|(&player_number, _)| player_number)
+    }
+
+    /// The first human player without a gamepad, bots can't be steered anyway
+    fn free_player(&self, number_of_players: usize, player_slots: &PlayerSlots) -> Option<u8> {
+        (1..=number_of_players)
+            .filter_map(|n| u8::try_from(n).ok())
+            .filter(|&player_number| player_slots.player(player_number) == PlayerSlot::Human)
+            .find(|player_number| !self.players.contains_key(player_number))
+    }
+}
+
+/// Left stick pushed towards `direction`
+pub(crate) fn stick_direction(direction: Direction) -> GamepadControlDirection {
+    let stick_direction = match direction {
+        Direction::Down => GamepadControlDirection::LEFT_DOWN,
+        Direction::Left => GamepadControlDirection::LEFT_LEFT,
+        Direction::Right => GamepadControlDirection::LEFT_RIGHT,
+        Direction::Up => GamepadControlDirection::LEFT_UP,
+    };
+    stick_direction.threshold(STICK_DEADZONE)
+}
+
+/// Plugged in gamepads wait to [`join`], unless they were unplugged from a player that still has none
+fn handle_connections(
+    mut connection_events: MessageReader<GamepadConnectionEvent>,
+    mut assignments: ResMut<GamepadAssignments>,
+) {
+    for event in connection_events.read() {
+        if event.disconnected() {
+            if let Some(player_number) = assignments.player(event.gamepad) {
+                info!("Gamepad of player {} disconnected", player_number);
+                assignments.players.remove(&player_number);
+                assignments
+                    .disconnected
+                    .insert(player_number, event.gamepad);
+            }
+            continue;
+        }
+
+        let returning = assignments
+            .disconnected
+            .iter()
+            .find(|(_, &gamepad)| gamepad == event.gamepad)
+            .map(|(&player_number, _)| player_number);
+        if let Some(player_number) = returning {
+            assignments.disconnected.remove(&player_number);
+            if let Entry::Vacant(entry) = assignments.players.entry(player_number) {
+                info!("Gamepad of player {} reconnected", player_number);
+                entry.insert(event.gamepad);
+            }
+            continue;
+        }
+
+        if assignments.player(event.gamepad).is_none() {
+            info!("Gamepad connected, press `{:?}` to join", JOIN_BUTTON);
+        }
+    }
+}
+
+/// Gives the first free player to any unassigned gamepad pressing [`JOIN_BUTTON`]
+fn join(
+    gamepads: Query<(Entity, &Gamepad)>,
+    mut assignments: ResMut<GamepadAssignments>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    player_slots: Res<PlayerSlots>,
+) {
+    for (entity, gamepad) in gamepads.iter() {
+        if !gamepad.just_pressed(JOIN_BUTTON) || assignments.player(entity).is_some() {
+            continue;
+        }
+        let Some(player_number) = assignments.free_player(number_of_players.0, &player_slots)
+        else {
+            continue;
+        };
+        info!("Gamepad joined as player {}", player_number);
+        assignments.disconnected.remove(&player_number);
+        assignments.players.insert(player_number, entity);
+    }
+}
+
+/// The input maps are bound to a gamepad when created, so they are removed for the input handler to add them again
+fn rebuild_input_maps(mut commands: Commands, snakes: Query<Entity, With<Snake>>) {
+    for snake in snakes.iter() {
+        commands
+            .entity(snake)
+            .remove::<(InputMap<Direction>, ActionState<Direction>)>();
+    }
+}
+
+fn gamepads_window(
+    mut contexts: EguiContexts,
+    mut assignments: ResMut<GamepadAssignments>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    names: Query<&Name, With<Gamepad>>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Gamepads").show(ctx, |ui| {
+        ui.label(format!(
+            "Press `{:?}` on a gamepad to join as the first free human player",
+            JOIN_BUTTON
+        ));
+        egui::Grid::new("gamepads").striped(true).show(ui, |ui| {
+            for player_number in (1..=number_of_players.0).filter_map(|n| u8::try_from(n).ok()) {
+                ui.label(format!("Player {}", player_number));
+                match assignments.gamepad(player_number) {
+                    Some(gamepad) => {
+                        ui.label(
+                            names
+                                .get(gamepad)
+                                .map(|name| name.to_string())
+                                .unwrap_or_else(|_| format!("{}", gamepad)),
+                        );
+                        if ui.button("Release").clicked() {
+                            assignments.players.remove(&player_number);
+                        }
+                    }
+                    None if assignments.disconnected.contains_key(&player_number) => {
+                        ui.label("Disconnected");
+                    }
+                    None => {
+                        ui.label("Keyboard");
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::gamepad::GamepadConnection;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_message::<GamepadConnectionEvent>()
+            .init_resource::<GamepadAssignments>()
+            .insert_resource(NumberOfPlayersSelected(2))
+            .init_resource::<PlayerSlots>()
+            .add_systems(Update, (handle_connections, join).chain());
+        app
+    }
+
+    fn connect(app: &mut App, gamepad: Entity, connected: bool) {
+        let connection = if connected {
+            GamepadConnection::Connected {
+                name: "Test".to_string(),
+                vendor_id: None,
+                product_id: None,
+            }
+        } else {
+            GamepadConnection::Disconnected
+        };
+        app.world_mut()
+            .write_message(GamepadConnectionEvent::new(gamepad, connection));
+        app.update();
+    }
+
+    fn press_join(app: &mut App, gamepad: Entity) {
+        let mut pad = app.world_mut().get_mut::<Gamepad>(gamepad).unwrap();
+        pad.digital_mut().press(JOIN_BUTTON);
+        app.update();
+        let mut pad = app.world_mut().get_mut::<Gamepad>(gamepad).unwrap();
+        pad.digital_mut().clear();
+    }
+
+    #[test]
+    fn gamepads_join_by_pressing_the_join_button() {
+        let mut app = app();
+        let gamepad = app.world_mut().spawn(Gamepad::default()).id();
+        connect(&mut app, gamepad, true);
+        assert_eq!(
+            app.world().resource::<GamepadAssignments>().gamepad(1),
+            None
+        );
+
+        press_join(&mut app, gamepad);
+        assert_eq!(
+            app.world().resource::<GamepadAssignments>().gamepad(1),
+            Some(gamepad)
+        );
+    }
+
+    #[test]
+    fn reconnected_gamepads_get_their_player_back() {
+        let mut app = app();
+        let first = app.world_mut().spawn(Gamepad::default()).id();
+        let second = app.world_mut().spawn(Gamepad::default()).id();
+        press_join(&mut app, first);
+        press_join(&mut app, second);
+
+        connect(&mut app, first, false);
+        assert_eq!(
+            app.world().resource::<GamepadAssignments>().gamepad(1),
+            None
+        );
+        connect(&mut app, first, true);
+        let assignments = app.world().resource::<GamepadAssignments>();
+        assert_eq!(assignments.gamepad(1), Some(first));
+        assert_eq!(assignments.gamepad(2), Some(second));
+    }
+}
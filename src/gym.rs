@@ -0,0 +1,468 @@
+//! A reinforcement learning environment in the style of Gym, playing the real game rules without a window
+//!
+//! ```no_run
+//! use snake_bevy::gym::{Config, Direction, Environment};
+//!
+//! let mut environment = Environment::default();
+//! let first = environment.reset(42, Config::default());
+//! assert_eq!(first.number_of_players, 2);
+//! loop {
+//!     let actions = [Some(Direction::Up), None];
+//!     let (_observation, rewards, done, info) = environment.step(&actions);
+//!     println!("Tick {}: {:?}", info.tick, rewards);
+//!     if done {
+//!         break;
+//!     }
+//! }
+//! ```
+
+use bevy::{ecs::message::MessageCursor, prelude::*};
+
+use crate::{
+    apple::{Apple, AppleEaten},
+    collision::Collision,
+    coordinate::Coordinate,
+    headless::{headless_app_with, step},
+    movement::{ProposeDirection, TickCount},
+    rules::{self, GameRules},
+    snake::{Id, Snake},
+    win::Won,
+    MAX_NUMBER_OF_PLAYERS,
+};
+
+pub use crate::direction::Direction;
+
+/// What each layer of an [`Observation`] has a 1 on, in order
+pub const CHANNELS: [&str; 5] = ["own body", "other bodies", "heads", "apples", "walls"];
+
+/// How an episode is played
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// From 1 to 4, each one gets its own action, observation and reward
+    pub number_of_players: usize,
+    /// Side of the square board in cells, odd and at least 5, sets the size of the observation
+    pub board_size: usize,
+    pub rewards: Rewards,
+    /// The episode is done after this many ticks even if nobody won, 0 for no limit
+    pub max_ticks: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            number_of_players: 2,
+            board_size: GameRules::default().board.side() as usize,
+            rewards: Rewards::default(),
+            max_ticks: 3000,
+        }
+    }
+}
+
+/// What each player gets for what happened to its snake in a step
+#[derive(Clone, Debug)]
+pub struct Rewards {
+    pub apple: f32,
+    /// For hitting something and losing part of the body
+    pub collision: f32,
+    pub win: f32,
+    /// For every other player when someone wins
+    pub lose: f32,
+    /// For every step, usually small to make snakes hurry up or keep going
+    pub step: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Self {
+            apple: 1.0,
+            collision: -1.0,
+            win: 10.0,
+            lose: -10.0,
+            step: 0.0,
+        }
+    }
+}
+
+/// The board as seen by every player, one grid tensor each
+#[derive(Clone, Debug)]
+pub struct Observation {
+    pub number_of_players: usize,
+    /// Side of the square board, in cells, from the rules of the episode
+    pub board_size: usize,
+    /// Shaped `[player][channel][y][x]` with the channels in [`CHANNELS`] order,
+    /// `x` going right and `y` going up from the bottom left corner of the board
+    pub tensor: Vec<f32>,
+}
+
+impl Observation {
+    fn new(number_of_players: usize, board_size: usize) -> Self {
+        Self {
+            number_of_players,
+            board_size,
+            tensor: vec![0.0; number_of_players * CHANNELS.len() * board_size * board_size],
+        }
+    }
+
+    fn player_len(&self) -> usize {
+        CHANNELS.len() * self.board_size * self.board_size
+    }
+
+    /// The `[channel][y][x]` tensor of the player at `index`, starting at 0 for player 1
+    pub fn player(&self, index: usize) -> &[f32] {
+        let player_len = self.player_len();
+        &self.tensor[index * player_len..(index + 1) * player_len]
+    }
+
+    pub fn get(&self, player: usize, channel: usize, x: usize, y: usize) -> f32 {
+        self.player(player)[(channel * self.board_size + y) * self.board_size + x]
+    }
+
+    /// Every cell is on the board, the snakes wrap around its edges
+    fn set(&mut self, player: usize, channel: usize, cell: IVec2) {
+        let half_len = (self.board_size / 2) as i32;
+        let (x, y) = ((cell.x + half_len) as usize, (cell.y + half_len) as usize);
+        let index =
+            player * self.player_len() + (channel * self.board_size + y) * self.board_size + x;
+        self.tensor[index] = 1.0;
+    }
+}
+
+/// Extra details of a step that aren't part of the observation
+#[derive(Clone, Debug, Default)]
+pub struct Info {
+    pub tick: u32,
+    /// By player
+    pub lengths: Vec<usize>,
+    /// Index of the player that won, if any
+    pub winner: Option<usize>,
+}
+
+const NOT_RESET: &str = "The environment has to be reset before stepping it";
+
+/// Runs episodes one step at a time, every step is one tick of the game
+/// Nothing is set up until the first [`Environment::reset`]
+#[derive(Default)]
+pub struct Environment {
+    app: Option<App>,
+    config: Config,
+    apples_eaten: MessageCursor<AppleEaten>,
+    collisions: MessageCursor<Collision>,
+    wins: MessageCursor<Won>,
+}
+
+impl Environment {
+    /// Starts a new episode, the same seed and config always play the same way given the same actions
+    pub fn reset(&mut self, seed: u64, mut config: Config) -> Observation {
+        config.number_of_players = config.number_of_players.clamp(1, MAX_NUMBER_OF_PLAYERS);
+        let half_len = (config.board_size / 2).max(rules::Board::MIN_HALF_LEN as usize);
+        let rules = GameRules {
+            board: rules::Board::new(half_len as i32),
+            ..default()
+        };
+        config.board_size = rules.board.side() as usize;
+        self.app = Some(headless_app_with(config.number_of_players, seed, |app| {
+            app.insert_resource(rules);
+        }));
+        self.config = config;
+        self.apples_eaten = default();
+        self.collisions = default();
+        self.wins = default();
+        self.observe().0
+    }
+
+    /// Plays one tick with an action for each player, `None` or a missing action keeps the snake going
+    /// Returns the new observation, the reward of each player, whether the episode is done and some extra details
+    pub fn step(&mut self, actions: &[Option<Direction>]) -> (Observation, Vec<f32>, bool, Info) {
+        let world = self.app.as_mut().expect(NOT_RESET).world_mut();
+        for (player_number, direction) in (1..=self.config.number_of_players as u8).zip(actions) {
+            if let Some(direction) = *direction {
+                world.write_message(ProposeDirection {
+                    id: Id(player_number),
+                    direction,
+                });
+            }
+        }
+        step(world);
+
+        let rewards = self.config.rewards.clone();
+        let mut player_rewards = vec![rewards.step; self.config.number_of_players];
+        let player_of = |world: &World, entity: Entity| {
+            let snake = world.get::<Snake>(entity)?;
+            usize::from(snake.player_number.0).checked_sub(1)
+        };
+        let eaten = self
+            .apples_eaten
+            .read(world.resource::<Messages<AppleEaten>>())
+            .map(|&AppleEaten(entity)| entity)
+            .collect::<Vec<_>>();
+        for player in eaten
+            .into_iter()
+            .filter_map(|entity| player_of(world, entity))
+        {
+            if let Some(reward) = player_rewards.get_mut(player) {
+                *reward += rewards.apple;
+            }
+        }
+        let hit = self
+            .collisions
+            .read(world.resource::<Messages<Collision>>())
+            .map(|&Collision(entity)| entity)
+            .collect::<Vec<_>>();
+        for player in hit
+            .into_iter()
+            .filter_map(|entity| player_of(world, entity))
+        {
+            if let Some(reward) = player_rewards.get_mut(player) {
+                *reward += rewards.collision;
+            }
+        }
+        let winner_name = self
+            .wins
+            .read(world.resource::<Messages<Won>>())
+            .last()
+            .map(|Won(name)| name.clone());
+
+        let (observation, mut info, names) = self.observe();
+        info.winner = winner_name.and_then(|winner| names.iter().position(|name| *name == winner));
+        if let Some(winner) = info.winner {
+            for (player, reward) in player_rewards.iter_mut().enumerate() {
+                *reward += if player == winner {
+                    rewards.win
+                } else {
+                    rewards.lose
+                };
+            }
+        }
+
+        let out_of_time = self.config.max_ticks > 0 && info.tick >= self.config.max_ticks;
+        let done = info.winner.is_some() || out_of_time;
+        (observation, player_rewards, done, info)
+    }
+
+    /// The observation, the details and the name of each player
+    fn observe(&mut self) -> (Observation, Info, Vec<String>) {
+        let number_of_players = self.config.number_of_players;
+        let world = self.app.as_mut().expect(NOT_RESET).world_mut();
+        let tick = world.resource::<TickCount>().0;
+        let board_size = world.resource::<GameRules>().board.side() as usize;
+        let board = world.run_system_cached(read_board).unwrap_or_default();
+
+        let mut observation = Observation::new(number_of_players, board_size);
+        let mut info = Info {
+            tick,
+            lengths: vec![0; number_of_players],
+            winner: None,
+        };
+        let mut names = vec![String::new(); number_of_players];
+        for (player, name, body) in board.snakes.iter() {
+            let Some(index) = usize::from(*player).checked_sub(1) else {
+                continue;
+            };
+            if index >= number_of_players {
+                continue;
+            }
+            info.lengths[index] = body.len();
+            names[index] = name.clone();
+            for viewer in 0..number_of_players {
+                let channel = if viewer == index { 0 } else { 1 };
+                for &cell in body.iter() {
+                    observation.set(viewer, channel, cell);
+                }
+                if let Some(&head) = body.first() {
+                    observation.set(viewer, 2, head);
+                }
+            }
+        }
+        // The board wraps around, the walls layer stays empty until there are maps with walls
+        for viewer in 0..number_of_players {
+            for &apple in board.apples.iter() {
+                observation.set(viewer, 3, apple);
+            }
+        }
+        (observation, info, names)
+    }
+}
+
+#[derive(Default)]
+struct Board {
+    /// Player number, name and body, head first
+    snakes: Vec<(u8, String, Vec<IVec2>)>,
+    apples: Vec<IVec2>,
+}
+
+fn read_board(
+    snakes: Query<&Snake>,
+    coordinates: Query<&Coordinate>,
+    apples: Query<&Coordinate, With<Apple>>,
+) -> Board {
+    let cell = |coordinate: &Coordinate| coordinate.0.round().as_ivec2();
+    Board {
+        snakes: snakes
+            .iter()
+            .map(|snake| {
+                let body = snake
+                    .segments
+                    .iter()
+                    .filter_map(|&segment| coordinates.get(segment).ok())
+                    .map(cell)
+                    .collect();
+                (snake.player_number.0, snake.name.clone(), body)
+            })
+            .collect(),
+        apples: apples.iter().map(cell).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apple::apple,
+        snake::{segment, MyColor},
+    };
+
+    const SEED: u64 = 7;
+
+    fn environment(config: Config) -> Environment {
+        let mut environment = Environment::default();
+        environment.reset(SEED, config);
+        environment
+    }
+
+    fn world(environment: &mut Environment) -> &mut World {
+        environment.app.as_mut().unwrap().world_mut()
+    }
+
+    /// The snake of `player_number`, where its head is and where it's heading
+    fn head(world: &mut World, player_number: u8) -> (Entity, Vec2, Vec2) {
+        let mut snakes = world.query::<(Entity, &Snake)>();
+        let (entity, snake) = snakes
+            .iter(world)
+            .find(|(_, snake)| snake.player_number.0 == player_number)
+            .unwrap();
+        let head = world.get::<Coordinate>(snake.segments[0]).unwrap().0;
+        (entity, head, snake.direction.into())
+    }
+
+    fn add_segment(world: &mut World, snake: Entity, cell: Vec2) {
+        let color = *world.get::<MyColor>(snake).unwrap();
+        let segment = world.spawn(segment(color, Coordinate(cell))).id();
+        world
+            .get_mut::<Snake>(snake)
+            .unwrap()
+            .segments
+            .push_back(segment);
+    }
+
+    /// So nobody eats or grows unless a test wants them to
+    fn remove_apples(world: &mut World) {
+        let apples = world
+            .query_filtered::<Entity, With<Apple>>()
+            .iter(world)
+            .collect::<Vec<_>>();
+        for apple in apples {
+            world.despawn(apple);
+        }
+    }
+
+    #[test]
+    fn the_same_seed_and_actions_play_the_same_way() {
+        let mut environments = [(); 2].map(|_| environment(Config::default()));
+        for tick in 0..300 {
+            let turn = (tick % 7 == 0).then(|| Direction::ALL[tick / 7 % Direction::ALL.len()]);
+            let [(first, first_rewards, first_done, _), (second, second_rewards, second_done, _)] =
+                environments
+                    .each_mut()
+                    .map(|environment| environment.step(&[turn, None]));
+            assert_eq!(first.tensor, second.tensor);
+            assert_eq!(first_rewards, second_rewards);
+            assert_eq!(first_done, second_done);
+        }
+    }
+
+    #[test]
+    fn the_observation_covers_the_board_of_the_config() {
+        let mut environment = environment(Config {
+            board_size: 21,
+            ..default()
+        });
+        let side = world(&mut environment).resource::<GameRules>().board.side() as usize;
+        assert_eq!(side, 21);
+        let (observation, ..) = environment.step(&[]);
+        assert_eq!(observation.board_size, side);
+        assert_eq!(observation.tensor.len(), 2 * CHANNELS.len() * side * side);
+        // Both heads are seen by both players
+        for player in 0..2 {
+            let heads = observation.player(player)[2 * side * side..3 * side * side]
+                .iter()
+                .sum::<f32>();
+            assert_eq!(heads, 2.0);
+        }
+    }
+
+    #[test]
+    fn apples_reward_the_player_that_eats_them() {
+        let mut environment = environment(Config::default());
+        let world = world(&mut environment);
+        remove_apples(world);
+        let (_, head, direction) = head(world, 2);
+        world.spawn(apple(Coordinate(head + direction)));
+
+        let (_, rewards, _, info) = environment.step(&[]);
+        assert_eq!(rewards, [0.0, Rewards::default().apple]);
+        assert_eq!(info.lengths, [1, 2]);
+    }
+
+    #[test]
+    fn collisions_punish_the_player_that_hits() {
+        let mut environment = environment(Config::default());
+        let world = world(&mut environment);
+        remove_apples(world);
+        let (_, head_1, direction_1) = head(world, 1);
+        let (snake_2, head_2, direction_2) = head(world, 2);
+        // The tail moves to the front, the segment before it stays in the way of player 1
+        add_segment(world, snake_2, head_1 + direction_1);
+        add_segment(world, snake_2, head_2 - direction_2);
+
+        let (_, rewards, _, _) = environment.step(&[]);
+        assert_eq!(rewards, [Rewards::default().collision, 0.0]);
+    }
+
+    #[test]
+    fn the_episode_is_done_when_someone_wins() {
+        let mut environment = environment(Config {
+            max_ticks: 0,
+            ..default()
+        });
+        let world = world(&mut environment);
+        remove_apples(world);
+        world.resource_mut::<GameRules>().length_to_win = 2;
+        let (snake_1, head_1, direction_1) = head(world, 1);
+        add_segment(world, snake_1, head_1 - direction_1);
+
+        let rewards = Rewards::default();
+        for _ in 0..1000 {
+            let (_, player_rewards, done, info) = environment.step(&[]);
+            if done {
+                assert_eq!(info.winner, Some(0));
+                assert_eq!(player_rewards, [rewards.win, rewards.lose]);
+                return;
+            }
+        }
+        panic!("Nobody won");
+    }
+
+    #[test]
+    fn the_episode_is_done_after_max_ticks() {
+        let mut environment = environment(Config {
+            max_ticks: 20,
+            ..default()
+        });
+        remove_apples(world(&mut environment));
+        for tick in 1..=20 {
+            let (_, _, done, info) = environment.step(&[]);
+            assert_eq!(info.tick, tick);
+            assert_eq!(done, tick == 20);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! The game rules without a window, rendering or input, to simulate matches as fast as possible
+//! Nothing runs on its own, every call to [`step`] is one tick
+
+use bevy::{
+    ecs::message::{message_update_system, MessageCursor},
+    prelude::*,
+    state::app::StatesPlugin,
+};
+
+use crate::{
+    apple::ApplePlugin,
+    collision::CollisionPlugin,
+    controls::InputBuffers,
+    game_state::GameStatePlugin,
+    main_menu::NumberOfPlayersSelected,
+    movement::{SnakeMovementPlugin, TickCount},
+    rng::{RngPlugin, Seed},
+    rules::RulesPlugin,
+    schedule::{run_tick, SchedulePlugin},
+    snake::{Snake, SnakePlugin},
+    snapshot::SnapshotPlugin,
+    win::{WinPlugin, Won},
+};
+
+/// Marks an app without a window, whose ticks only run when stepped
+#[derive(Resource)]
+pub(crate) struct Headless;
+
+/// Same as [`headless_app_with`] with nothing else to set up
+#[cfg(test)]
+pub(crate) fn headless_app(number_of_players: usize, seed: u64) -> App {
+    headless_app_with(number_of_players, seed, |_| {})
+}
+
+/// An app in the middle of a match, with the board spawned from `seed`
+/// `setup` adds more plugins or resources before the match starts
+pub(crate) fn headless_app_with(
+    number_of_players: usize,
+    seed: u64,
+    setup: impl FnOnce(&mut App),
+) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        GameStatePlugin,
+        SchedulePlugin,
+        SnakePlugin,
+        SnakeMovementPlugin,
+        ApplePlugin,
+        CollisionPlugin,
+        WinPlugin,
+        RngPlugin,
+        SnapshotPlugin,
+        RulesPlugin,
+    ))
+    .insert_resource(NumberOfPlayersSelected(number_of_players))
+    .insert_resource(Headless)
+    .init_resource::<InputBuffers>();
+    setup(&mut app);
+    app.finish();
+    app.cleanup();
+
+    let world = app.world_mut();
+    world.resource_mut::<Seed>().0 = seed;
+    // The default state is in game, entering it spawns the board
+    world.run_schedule(StateTransition);
+    app
+}
+
+/// Runs one tick of the match
+pub(crate) fn step(world: &mut World) {
+    run_tick(world);
+    // Normally done once per frame, otherwise messages would pile up
+    let _ = world.run_system_cached(message_update_system);
+}
+
+/// Steps until someone wins or the match reaches `max_ticks`, calling `after_tick` after every tick
+/// Returns the player number of the winner, `None` for a draw
+pub(crate) fn play_until_won(
+    world: &mut World,
+    max_ticks: u32,
+    mut after_tick: impl FnMut(&mut World),
+) -> Option<u8> {
+    let mut wins = MessageCursor::<Won>::default();
+    while world.resource::<TickCount>().0 < max_ticks {
+        step(world);
+        after_tick(world);
+
+        let Some(Won(name)) = wins.read(world.resource::<Messages<Won>>()).last() else {
+            continue;
+        };
+        let name = name.clone();
+        let mut snakes = world.query::<&Snake>();
+        return snakes
+            .iter(world)
+            .find(|snake| snake.name == name)
+            .map(|snake| snake.player_number.0);
+    }
+    None
+}
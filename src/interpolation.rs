@@ -0,0 +1,122 @@
+//! Optionally draws snakes sliding between cells instead of jumping from one to the next
+//! Only the transforms are tweened, the coordinates stay on the grid
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{
+    coordinate::Coordinate,
+    game_state::InMatch,
+    movement::TickCount,
+    rules::GameRules,
+    schedule::{tick_gate_open, InGameSet, TickSet},
+    snake::{update_local_coordinates_to_world_transforms, Snake},
+    storage::Storage,
+};
+
+const SMOOTH_MOVEMENT_KEY: &str = "smooth_movement";
+
+pub(crate) struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SmoothMovement>()
+            .add_systems(Startup, load_smooth_movement)
+            .add_systems(FixedUpdate, remember_cells.in_set(TickSet::Input))
+            .add_systems(
+                Update,
+                interpolate
+                    .after(update_local_coordinates_to_world_transforms)
+                    .in_set(InGameSet::Last)
+                    // A closed gate holds the ticks back, so the snakes stay on their cells
+                    .run_if(
+                        in_state(InMatch)
+                            .and(tick_gate_open)
+                            .and(|smooth: Res<SmoothMovement>| smooth.0),
+                    ),
+            );
+    }
+}
+
+/// Picked in the motion settings of the effects, next to reducing motion
+#[derive(Resource, Default)]
+pub(crate) struct SmoothMovement(bool);
+
+impl SmoothMovement {
+    pub(crate) fn checkbox(&mut self, ui: &mut egui::Ui, storage: &Storage) {
+        if ui.checkbox(&mut self.0, "Smooth movement").changed() {
+            storage.save(SMOOTH_MOVEMENT_KEY, &self.0);
+        }
+    }
+}
+
+/// Cells of each segment of the snake before the last tick, by index from the head
+/// The tail moves to the front on every tick, so the segment at an index is what slides
+#[derive(Component)]
+struct PreviousCells {
+    /// Tick count when they were remembered, one less than after the tick
+    tick: u32,
+    cells: Vec<Vec2>,
+}
+
+fn load_smooth_movement(mut smooth_movement: ResMut<SmoothMovement>, storage: Res<Storage>) {
+    smooth_movement.0 = storage.load(SMOOTH_MOVEMENT_KEY).unwrap_or_default();
+}
+
+fn remember_cells(
+    mut commands: Commands,
+    mut snakes: Query<(Entity, &Snake, Option<&mut PreviousCells>)>,
+    coordinates: Query<&Coordinate>,
+    tick_count: Res<TickCount>,
+) {
+    for (entity, snake, previous) in snakes.iter_mut() {
+        let cells = snake
+            .segments
+            .iter()
+            .filter_map(|&segment| coordinates.get(segment).ok())
+            .map(|coordinate| coordinate.0)
+            .collect();
+        let remembered = PreviousCells {
+            tick: tick_count.0,
+            cells,
+        };
+        match previous {
+            Some(mut previous) => *previous = remembered,
+            None => {
+                commands.entity(entity).insert(remembered);
+            }
+        }
+    }
+}
+
+/// Moves every segment from its cell before the tick towards its cell now, as far as the next tick is
+/// A segment that wrapped around slides off one edge instead of across the board
+fn interpolate(
+    snakes: Query<(&Snake, &PreviousCells)>,
+    mut segments: Query<(&Coordinate, &mut Transform)>,
+    fixed_time: Res<Time<Fixed>>,
+    tick_count: Res<TickCount>,
+    rules: Res<GameRules>,
+) {
+    let progress = fixed_time.overstep_fraction().clamp(0.0, 1.0);
+    let half_len = rules.board.half_len as f32;
+    let side = Vec2::splat(rules.board.side() as f32);
+    for (snake, previous) in snakes.iter() {
+        // Restoring a snapshot or rewinding changes the tick without a move to slide along
+        if previous.tick + 1 != tick_count.0 {
+            continue;
+        }
+        for (&segment, &from) in snake.segments.iter().zip(previous.cells.iter()) {
+            let Ok((coordinate, mut transform)) = segments.get_mut(segment) else {
+                continue;
+            };
+            let step = (coordinate.0 - from + half_len).rem_euclid(side) - half_len;
+            // Anything further, like rewinding, jumps
+            if step.abs().element_sum() > 1.0 {
+                continue;
+            }
+            let position = coordinate.0 - step * (1.0 - progress);
+            transform.translation = position.extend(transform.translation.z);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Options to launch the game with, from the command line or from the query of the URL on the web
+//! Like `snake_bevy --players 2 --player 2=hard --board-size 21 --start match`
+//! or `index.html?players=2&player=2=hard&board-size=21&start=match`
+
+use bevy::prelude::*;
+
+use crate::{
+    ai::Difficulty,
+    game_state::AppState,
+    main_menu::{NumberOfPlayersSelected, PlayerSlot},
+    rng::Seed,
+    rules::{Board, GameRules, LocalRules},
+    snapshot::{GameSnapshot, StartingSnapshot},
+    MAX_NUMBER_OF_PLAYERS,
+};
+
+pub(crate) const USAGE: &str = "Usage: snake_bevy [--players N] [--player N=human|easy|medium|hard|external|PRESET]... [--board-size SIDE | --map PATH] [--scenario PATH] [--seed S] [--tick-rate TICKS_PER_SECOND] [--win-length LENGTH] [--win-hold SECONDS] [--windowed | --fullscreen] [--start menu|match]";
+
+/// Applies the options over what the other plugins set up, so it has to be added after them
+/// Options that can't be parsed are ignored with a warning, natively the game doesn't even start
+pub(crate) struct LaunchPlugin(pub(crate) Result<LaunchOptions, String>);
+
+impl Plugin for LaunchPlugin {
+    fn build(&self, app: &mut App) {
+        let options = match &self.0 {
+            Ok(options) => options.clone(),
+            Err(error) => {
+                let error = error.clone();
+                app.add_systems(Startup, move || {
+                    warn!("Ignoring the launch options: {}", error)
+                });
+                return;
+            }
+        };
+        let mut rules = GameRules::default();
+        options.override_rules(&mut rules);
+        app.insert_resource(rules.clone())
+            .insert_resource(LocalRules(rules));
+        // A scenario has its own snakes
+        let number_of_players = options.number_of_players.or(options
+            .scenario
+            .as_ref()
+            .map(|scenario| scenario.snakes.len()));
+        if let Some(number_of_players) = number_of_players {
+            app.insert_resource(NumberOfPlayersSelected(number_of_players));
+        }
+        if let Some(scenario) = &options.scenario {
+            app.insert_resource(StartingSnapshot(Some(scenario.clone())));
+        }
+        // Only the first match, every match after it picks a new one
+        if let Some(seed) = options.seed {
+            app.insert_resource(Seed(seed));
+        }
+        if let Some(start) = options.start.clone() {
+            app.insert_state(start);
+        }
+        // The player slots are stored, these replace them without saving in `load_player_slots`
+        app.insert_resource(options);
+    }
+}
+
+#[derive(Resource, Clone, Default)]
+pub(crate) struct LaunchOptions {
+    pub(crate) number_of_players: Option<usize>,
+    /// By player number starting at 1
+    pub(crate) slots: Vec<(u8, PlayerSlot)>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) board: Option<Board>,
+    /// Board to start from, see [`StartingSnapshot`]
+    pub(crate) scenario: Option<GameSnapshot>,
+    pub(crate) tick_seconds: Option<f64>,
+    pub(crate) length_to_win: Option<usize>,
+    pub(crate) hold_time_to_win: Option<f32>,
+    /// Fullscreen otherwise, the web is always in the page
+    pub(crate) windowed: bool,
+    pub(crate) start: Option<AppState>,
+}
+
+impl LaunchOptions {
+    /// Natively from the command line
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_environment() -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut arguments = std::env::args().skip(1);
+        while let Some(argument) = arguments.next() {
+            let key = argument
+                .strip_prefix("--")
+                .ok_or_else(|| format!("Unexpected argument {}", argument))?;
+            match key {
+                "windowed" => options.windowed = true,
+                "fullscreen" => options.windowed = false,
+                _ => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| format!("Missing the value of {}", argument))?;
+                    // Read by the bots themselves
+                    if argument != crate::bots::BOT_ARGUMENT {
+                        options.set(key, &value)?;
+                    }
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// On the web from the query of the URL, like `?players=2&seed=42`
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn from_environment() -> Result<Self, String> {
+        let mut options = Self::default();
+        let query = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+        for pair in query.trim_start_matches('?').split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            options.set(&decode(key), &decode(value))?;
+        }
+        Ok(options)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "players" => {
+                let number_of_players = number(value)?;
+                if !(1..=MAX_NUMBER_OF_PLAYERS).contains(&number_of_players) {
+                    return Err(format!(
+                        "There can be from 1 to {} players",
+                        MAX_NUMBER_OF_PLAYERS
+                    ));
+                }
+                self.number_of_players = Some(number_of_players);
+            }
+            "player" => {
+                let (player_number, slot) = value.split_once('=').ok_or_else(|| {
+                    format!("Invalid player {}, it should look like 2=hard", value)
+                })?;
+                let player_number = number(player_number)?;
+                if !(1..=MAX_NUMBER_OF_PLAYERS).contains(&usize::from(player_number)) {
+                    return Err(format!("There is no player {}", player_number));
+                }
+                self.slots.push((player_number, player_slot(slot)));
+            }
+            "board-size" => {
+                let side: i32 = number(value)?;
+                if side % 2 == 0 || side < 2 * Board::MIN_HALF_LEN + 1 {
+                    return Err(format!(
+                        "The board size has to be odd and at least {}",
+                        2 * Board::MIN_HALF_LEN + 1
+                    ));
+                }
+                self.board = Some(Board::new(side / 2));
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            "map" => self.board = Some(Board::load(value)?),
+            #[cfg(not(target_arch = "wasm32"))]
+            "scenario" => self.scenario = Some(GameSnapshot::load(value)?),
+            "seed" => self.seed = Some(number(value)?),
+            "tick-rate" => {
+                let ticks_per_second: f64 = number(value)?;
+                if !ticks_per_second.is_finite() || ticks_per_second <= 0.0 {
+                    return Err("The tick rate has to be above 0".to_string());
+                }
+                self.tick_seconds = Some(ticks_per_second.recip());
+            }
+            "win-length" => self.length_to_win = Some(number(value)?),
+            "win-hold" => {
+                let seconds: f32 = number(value)?;
+                if !seconds.is_finite() || seconds < 0.0 {
+                    return Err("The time to hold the lead can't be negative".to_string());
+                }
+                self.hold_time_to_win = Some(seconds);
+            }
+            "start" => {
+                self.start = Some(match value {
+                    "menu" => AppState::MainMenu,
+                    "match" => AppState::InGame,
+                    _ => return Err(format!("Can't start in {}, only menu or match", value)),
+                })
+            }
+            _ => return Err(format!("Unknown option {}", key)),
+        }
+        Ok(())
+    }
+
+    /// The rules given when launching win over the ones in the rules file
+    pub(crate) fn override_rules(&self, rules: &mut GameRules) {
+        if let Some(board) = &self.board {
+            rules.board = board.clone();
+        }
+        if let Some(tick_seconds) = self.tick_seconds {
+            rules.tick_seconds = tick_seconds;
+        }
+        if let Some(length_to_win) = self.length_to_win {
+            rules.length_to_win = length_to_win;
+        }
+        if let Some(hold_time_to_win) = self.hold_time_to_win {
+            rules.hold_time_to_win = hold_time_to_win;
+        }
+    }
+}
+
+/// Any other name is an AI preset
+fn player_slot(name: &str) -> PlayerSlot {
+    match name {
+        "human" => PlayerSlot::Human,
+        "easy" => PlayerSlot::Bot(Difficulty::Easy),
+        "medium" => PlayerSlot::Bot(Difficulty::Medium),
+        "hard" => PlayerSlot::Bot(Difficulty::Hard),
+        "external" => PlayerSlot::External,
+        preset => PlayerSlot::Bot(Difficulty::Preset(preset.to_string())),
+    }
+}
+
+/// Parses the value of a numeric option, for the game and the headless tools
+pub(crate) fn number<T: std::str::FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|error| format!("Invalid number {}: {}", value, error))
+}
+
+/// Undoes the percent encoding of a part of the query
+#[cfg(target_arch = "wasm32")]
+fn decode(encoded: &str) -> String {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(if byte == b'+' { b' ' } else { byte });
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
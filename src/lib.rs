@@ -0,0 +1,184 @@
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::window::{MonitorSelection, WindowMode};
+use bevy::{asset::AssetMetaCheck, prelude::*};
+use movement::ProposeDirection;
+
+mod coordinate;
+
+mod direction;
+use direction::Direction;
+
+mod main_menu;
+use main_menu::MainMenu;
+
+mod game_state;
+use game_state::GameStatePlugin;
+
+mod ai;
+use ai::AIPlugin;
+
+mod asset_loader;
+use asset_loader::AssetLoaderPlugin;
+
+mod theme;
+use theme::ThemePlugin;
+
+mod movement;
+use movement::SnakeMovementPlugin;
+
+mod score;
+use score::ScorePlugin;
+
+mod win;
+use win::WinPlugin;
+
+mod apple;
+use apple::ApplePlugin;
+
+mod collision;
+use collision::CollisionPlugin;
+
+mod blink;
+
+mod schedule;
+use schedule::SchedulePlugin;
+
+mod snake;
+use snake::SnakePlugin;
+
+mod interpolation;
+use interpolation::InterpolationPlugin;
+
+mod effects;
+use effects::EffectsPlugin;
+
+mod storage;
+use storage::StoragePlugin;
+
+mod records;
+use records::RecordsPlugin;
+
+mod rng;
+use rng::RngPlugin;
+
+mod replay;
+use replay::ReplayPlugin;
+
+mod snapshot;
+use snapshot::SnapshotPlugin;
+
+mod rewind;
+use rewind::RewindPlugin;
+
+mod controls;
+use controls::ControlsPlugin;
+
+mod gamepads;
+use gamepads::GamepadsPlugin;
+
+mod touch;
+use touch::TouchPlugin;
+
+mod headless;
+
+mod rules;
+use rules::RulesPlugin;
+
+mod launch;
+use launch::{LaunchOptions, LaunchPlugin};
+
+pub mod gym;
+
+pub mod evolve;
+
+// Runs external bots, which browsers can't
+#[cfg(not(target_arch = "wasm32"))]
+pub mod arena;
+
+// Browsers can't open sockets
+#[cfg(not(target_arch = "wasm32"))]
+mod net;
+
+// Nor run other programs
+#[cfg(not(target_arch = "wasm32"))]
+mod bots;
+
+const HALF_LEN: i32 = 7;
+const PADDING: f32 = 1.0;
+
+const MAX_NUMBER_OF_PLAYERS: usize = 4;
+
+/// Opens the game
+pub fn run() {
+    let launch_options = LaunchOptions::from_environment();
+    // There's no terminal to show it in on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = &launch_options {
+        eprintln!("{}\n{}", error, launch::USAGE);
+        std::process::exit(1);
+    }
+
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(AssetPlugin {
+                meta_check: AssetMetaCheck::Never, // https://github.com/bevyengine/bevy/issues/10157#issuecomment-2217168402
+                ..default()
+            })
+            .set(ImagePlugin::default_nearest())
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    // Borderless looks distorted when running in the web
+                    #[cfg(not(target_arch = "wasm32"))]
+                    mode: if launch_options
+                        .as_ref()
+                        .is_ok_and(|options| options.windowed)
+                    {
+                        WindowMode::Windowed
+                    } else {
+                        WindowMode::BorderlessFullscreen(MonitorSelection::Primary)
+                    },
+                    fit_canvas_to_parent: true,
+                    prevent_default_event_handling: false,
+                    resizable: true,
+                    ..default()
+                }),
+                ..default()
+            }),
+        SnakePlugin,
+        SnakeMovementPlugin,
+        MainMenu {
+            max_number_of_players: MAX_NUMBER_OF_PLAYERS,
+        },
+        GameStatePlugin,
+        AssetLoaderPlugin,
+        ScorePlugin,
+        WinPlugin,
+        ApplePlugin,
+        CollisionPlugin,
+        SchedulePlugin,
+        StoragePlugin,
+        RecordsPlugin,
+        RngPlugin,
+        ReplayPlugin,
+    ))
+    .add_plugins((
+        RulesPlugin,
+        ThemePlugin,
+        InterpolationPlugin,
+        EffectsPlugin,
+        SnapshotPlugin,
+        RewindPlugin,
+        ControlsPlugin,
+        GamepadsPlugin,
+        TouchPlugin,
+        AIPlugin,
+    ));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugins((net::NetPlugin, bots::BotsPlugin));
+
+    app.add_plugins(LaunchPlugin(launch_options));
+
+    app.run();
+}
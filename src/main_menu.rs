@@ -1,6 +1,13 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::win::Won;
+use crate::{
+    ai::{AiPresets, Difficulty},
+    controls::KeyBindings,
+    launch::LaunchOptions,
+    storage::Storage,
+    win::Won,
+};
 
 use super::game_state::AppState;
 
@@ -15,14 +22,21 @@ impl Plugin for MainMenu {
         app.add_plugins(EguiPlugin::default())
             .insert_resource(MaxNumberOfPlayers(self.max_number_of_players))
             .insert_resource(NumberOfPlayersSelected(self.max_number_of_players))
+            .init_resource::<PlayerSlots>()
+            .add_systems(Startup, load_player_slots)
             .add_systems(EguiPrimaryContextPass, selection.run_if(in_state(AppState::MainMenu)))
             .add_systems(EguiPrimaryContextPass, how_to_play)
-            .add_systems(Update, winner_text)
+            .add_systems(Update, winner_text.run_if(in_state(AppState::InGame)))
             .add_systems(OnExit(AppState::MainMenu), remove_winner_text);
     }
 }
 
-fn how_to_play(mut contexts: EguiContexts) {
+fn how_to_play(
+    mut contexts: EguiContexts,
+    key_bindings: Res<KeyBindings>,
+    number_of_players_selected: Res<NumberOfPlayersSelected>,
+    player_slots: Res<PlayerSlots>,
+) {
     let Ok(ctx) = contexts.ctx_mut() else {
         return;
     };
@@ -31,10 +45,23 @@ fn how_to_play(mut contexts: EguiContexts) {
         .show(ctx, |ui| {
             ui.label("`Esc` escape key to open the menu");
             ui.label("`Esc` escape key to get back into the game");
-            ui.label("`Arrow keys` to move player 1");
-            ui.label("`WASD` to move player 2");
-            ui.label("`IJKL` to move player 3");
-            ui.label("`Numpad 8456` to move player 4");
+            for player_number in
+                (1..=number_of_players_selected.0).filter_map(|n| u8::try_from(n).ok())
+            {
+                let slot = player_slots.player(player_number);
+                if slot != PlayerSlot::Human {
+                    ui.label(format!(
+                        "Player {} is played by the {}",
+                        player_number, slot
+                    ));
+                    continue;
+                }
+                let bindings = key_bindings
+                    .player(player_number)
+                    .map(|bindings| bindings.describe())
+                    .unwrap_or_else(|| "No keys".to_string());
+                ui.label(format!("`{}` to move player {}", bindings, player_number));
+            }
         });
 }
 
@@ -42,6 +69,9 @@ fn selection(
     mut contexts: EguiContexts,
     mut number_of_players_selected: ResMut<NumberOfPlayersSelected>,
     max_number_of_players: Res<MaxNumberOfPlayers>,
+    mut player_slots: ResMut<PlayerSlots>,
+    presets: Res<AiPresets>,
+    storage: Res<Storage>,
 ) {
     let Ok(ctx) = contexts.ctx_mut() else {
         return;
@@ -56,15 +86,126 @@ fn selection(
             .text("Number of players"),
         );
         ui.label(format!("{} players selected", number_of_players_selected.0));
+
+        let mut changed = false;
+        egui::Grid::new("player_slots").show(ui, |ui| {
+            for player_number in
+                (1..=number_of_players_selected.0).filter_map(|n| u8::try_from(n).ok())
+            {
+                ui.label(format!("Player {}", player_number));
+                let mut slot = player_slots.player(player_number);
+                egui::ComboBox::from_id_salt(("player_slot", player_number))
+                    .selected_text(slot.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in PlayerSlot::options(&presets) {
+                            let text = option.to_string();
+                            ui.selectable_value(&mut slot, option, text);
+                        }
+                    });
+                if slot != player_slots.player(player_number) {
+                    player_slots.set(player_number, slot);
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        });
+        if changed {
+            storage.save(PLAYER_SLOTS_KEY, &*player_slots);
+        }
     });
 }
 
 #[derive(Resource)]
-struct MaxNumberOfPlayers(usize);
+pub(crate) struct MaxNumberOfPlayers(usize);
 
 #[derive(Resource)]
 pub struct NumberOfPlayersSelected(pub usize);
 
+const PLAYER_SLOTS_KEY: &str = "player_slots";
+
+/// Who plays each snake
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum PlayerSlot {
+    #[default]
+    Human,
+    Bot(Difficulty),
+    /// A program of its own, see `bots`
+    External,
+}
+
+impl PlayerSlot {
+    fn options(presets: &AiPresets) -> Vec<PlayerSlot> {
+        let mut options = vec![PlayerSlot::Human];
+        options.extend(Difficulty::ALL.map(PlayerSlot::Bot));
+        options.extend(
+            presets
+                .0
+                .keys()
+                .map(|name| PlayerSlot::Bot(Difficulty::Preset(name.clone()))),
+        );
+        // Browsers can't run other programs
+        #[cfg(not(target_arch = "wasm32"))]
+        options.push(PlayerSlot::External);
+        options
+    }
+}
+
+impl std::fmt::Display for PlayerSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerSlot::Human => write!(f, "Human"),
+            PlayerSlot::Bot(difficulty) => write!(f, "{} bot", difficulty),
+            PlayerSlot::External => write!(f, "External bot"),
+        }
+    }
+}
+
+/// Who plays each snake, by player number starting at 1
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct PlayerSlots(pub(crate) Vec<PlayerSlot>);
+
+impl PlayerSlots {
+    pub(crate) fn player(&self, player_number: u8) -> PlayerSlot {
+        usize::from(player_number)
+            .checked_sub(1)
+            .and_then(|index| self.0.get(index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set(&mut self, player_number: u8, slot: PlayerSlot) {
+        let index = usize::from(player_number.max(1)) - 1;
+        if self.0.len() <= index {
+            self.0.resize_with(index + 1, default);
+        }
+        self.0[index] = slot;
+    }
+}
+
+pub(crate) fn load_player_slots(
+    mut player_slots: ResMut<PlayerSlots>,
+    max_number_of_players: Res<MaxNumberOfPlayers>,
+    storage: Res<Storage>,
+    launch_options: Option<Res<LaunchOptions>>,
+) {
+    *player_slots = storage.load(PLAYER_SLOTS_KEY).unwrap_or_default();
+
+    // From before the slots could be picked in the menu, makes every player a bot without saving it
+    if std::env::var("AI").is_ok_and(|ai| ai == "true") {
+        for player_number in (1..=max_number_of_players.0).filter_map(|n| u8::try_from(n).ok()) {
+            player_slots.set(player_number, PlayerSlot::Bot(Difficulty::Medium));
+        }
+    }
+
+    // Also without saving them
+    for (player_number, slot) in launch_options
+        .iter()
+        .flat_map(|options| options.slots.iter())
+    {
+        player_slots.set(*player_number, slot.clone());
+    }
+}
+
 #[derive(Component)]
 struct WinnerText;
 
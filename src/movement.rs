@@ -1,73 +1,83 @@
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
 
-use crate::{coordinate::Coordinate, game_state, snake::Snake, Direction, Id};
-
-const SNAKE_TICK_SECONDS: f32 = 0.1;
+use crate::{
+    controls::{Binding, InputBuffers, KeyBindings, WhenFull},
+    coordinate::Coordinate,
+    game_state::{self, InMatch},
+    gamepads::{stick_direction, GamepadAssignments},
+    main_menu::{PlayerSlot, PlayerSlots},
+    rules::GameRules,
+    schedule::{MatchSetupSet, TickSet},
+    snake::{Id, Snake},
+    Direction,
+};
 
 pub(crate) struct SnakeMovementPlugin;
 
 impl Plugin for SnakeMovementPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(SnakeTimer(Timer::from_seconds(
-            SNAKE_TICK_SECONDS,
-            TimerMode::Repeating,
-        )))
-        .add_plugins(InputManagerPlugin::<Direction>::default())
-        .add_message::<ProposeDirection>()
-        .add_message::<Tick>()
-        .add_systems(
-            Update,
-            (
-                tick,
-                input_snake_direction,
-                handle_snake_direction,
-                add_snake_input_handler,
+        app.init_resource::<TickCount>()
+            .add_message::<ProposeDirection>()
+            .add_message::<Tick>()
+            .add_systems(
+                OnEnter(InMatch),
+                (reset_tick_count, set_tick_rate).in_set(MatchSetupSet::Spawn),
             )
-                .run_if(in_state(game_state::AppState::InGame)),
-        );
+            .add_systems(FixedUpdate, handle_snake_direction.in_set(TickSet::Input))
+            .add_systems(FixedUpdate, tick.in_set(TickSet::Movement))
+            .add_systems(
+                Update,
+                (input_snake_direction, add_snake_input_handler)
+                    .run_if(in_state(game_state::AppState::InGame)),
+            );
     }
 }
 
-#[derive(Resource)]
-struct SnakeTimer(Timer);
-
 #[derive(Message)]
 pub(crate) struct Tick;
 
+/// Number of ticks since the match started
+#[derive(Resource, Default)]
+pub(crate) struct TickCount(pub(crate) u32);
+
+fn reset_tick_count(mut tick_count: ResMut<TickCount>) {
+    tick_count.0 = 0;
+}
+
+fn set_tick_rate(mut fixed_time: ResMut<Time<Fixed>>, rules: Res<GameRules>) {
+    fixed_time.set_timestep_seconds(rules.tick_seconds);
+}
+
 fn tick(
-    time: Res<Time>,
-    mut timer: ResMut<SnakeTimer>,
+    mut tick_count: ResMut<TickCount>,
     mut query: Query<&mut Snake>,
     mut entity_query: Query<&mut Coordinate>,
     mut tick: MessageWriter<Tick>,
 ) {
-    if timer.0.tick(time.delta()).just_finished() {
-        tick.write(Tick);
-        query
-            .iter_mut()
-            .flat_map(|mut snake| {
-                // Pop and apply the next queued direction if available
-                if let Some(next_direction) = snake.next_directions.pop_front() {
-                    snake.direction = next_direction;
-                }
-
-                let &tail_entity = snake.segments.back()?;
-                let &head_entity = snake.segments.front()?;
+    tick_count.0 += 1;
+    tick.write(Tick);
+    query
+        .iter_mut()
+        .flat_map(|mut snake| {
+            // Pop and apply the next queued direction if available
+            if let Some(next_direction) = snake.next_directions.pop_front() {
+                snake.direction = next_direction;
+            }
 
-                let head = entity_query.get_mut(head_entity).unwrap();
+            let &tail_entity = snake.segments.back()?;
+            let &head_entity = snake.segments.front()?;
 
-                let head_translation = head.0;
+            let head_translation = entity_query.get(head_entity).ok()?.0;
 
-                if let Ok(mut tail) = entity_query.get_mut(tail_entity) {
-                    snake.trail = Coordinate(tail.0); // TODO: remove double conversion
-                    tail.0 = head_translation + Into::<Vec2>::into(snake.direction.clone());
-                    snake.segments.rotate_right(1);
-                }
-                Some(())
-            })
-            .for_each(|_| ());
-    }
+            if let Ok(mut tail) = entity_query.get_mut(tail_entity) {
+                snake.trail = Coordinate(tail.0); // TODO: remove double conversion
+                tail.0 = head_translation + Into::<Vec2>::into(snake.direction.clone());
+                snake.segments.rotate_right(1);
+            }
+            Some(())
+        })
+        .for_each(|_| ());
 }
 
 /// This event proposes a direction for the snake
@@ -87,109 +97,96 @@ fn add_snake_input_handler(
             Without<ActionState<Direction>>,
         ),
     >,
+    key_bindings: Res<KeyBindings>,
+    gamepad_assignments: Res<GamepadAssignments>,
+    player_slots: Res<PlayerSlots>,
 ) {
     for (entity, snake) in snakes.iter() {
         let Ok(mut entity) = commands.get_entity(entity) else {
             continue;
         };
-        {
-            // VIM ordering
-            let directions = [
-                Direction::Left,
-                Direction::Down,
-                Direction::Up,
-                Direction::Right,
-            ];
-
-            let player_keys = match snake.player_number.0 {
-                1 => [
-                    KeyCode::ArrowLeft,
-                    KeyCode::ArrowDown,
-                    KeyCode::ArrowUp,
-                    KeyCode::ArrowRight,
-                ],
-                2 => [KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyW, KeyCode::KeyD],
-                3 => [KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyI, KeyCode::KeyL],
-                4 => [
-                    KeyCode::Numpad4,
-                    KeyCode::Numpad5,
-                    KeyCode::Numpad8,
-                    KeyCode::Numpad6,
-                ],
-                other => panic!("Invalid player number {}, only 1-4 supported", other),
-            };
-
-            // TODO: handle gamepad controls for more players
-            // Currently only player 1 is supported
-            // You'll also need to add logic to handle the Gamepad Id in the input map below
-            let player_gamepad = match snake.player_number.0 {
-                1 => [
-                    vec![GamepadButton::DPadLeft],
-                    vec![GamepadButton::DPadDown],
-                    vec![GamepadButton::DPadUp],
-                    vec![GamepadButton::DPadRight],
-                ],
-                2 => [vec![], vec![], vec![], vec![]],
-                3 => [vec![], vec![], vec![], vec![]],
-                4 => [vec![], vec![], vec![], vec![]],
-                other => panic!("Invalid player number {}, only 1-4 supported", other),
-            };
-
-            let mut input_map = InputMap::default();
-
-            for (player_controls, direction) in std::iter::zip(player_keys, directions) {
-                input_map.insert(direction, player_controls);
-            }
 
-            for (player_controls, direction) in std::iter::zip(player_gamepad, directions) {
-                input_map.insert_one_to_many(direction, player_controls);
-            }
+        let mut input_map = InputMap::default();
+        // Bots can't be steered by whoever shares the keyboard or picks up a gamepad
+        let human = player_slots.player(snake.player_number.0) == PlayerSlot::Human;
+        let gamepad = gamepad_assignments
+            .gamepad(snake.player_number.0)
+            .filter(|_| human);
 
-            // Note: In Bevy 0.15, Gamepad uses Entity-based IDs.
-            // leafwing-input-manager 0.16 handles this automatically, so we don't need to set a gamepad here.
-            // The input map will work with the first connected gamepad by default.
+        // Players without bindings can still be moved by the AI
+        if let Some(bindings) = key_bindings.player(snake.player_number.0).filter(|_| human) {
+            for direction in Direction::ALL {
+                for binding in bindings.get(direction) {
+                    match *binding {
+                        Binding::Key(key) => {
+                            input_map.insert(direction, key);
+                        }
+                        // Without a gamepad of its own, any gamepad would drive this player
+                        Binding::Button(button) if gamepad.is_some() => {
+                            input_map.insert(direction, button);
+                        }
+                        Binding::Button(_) => {}
+                    }
+                }
+            }
+        }
 
-            // In Bevy 0.16, insert InputMap and ActionState directly instead of using InputManagerBundle
-            entity.insert((
-                input_map,
-                ActionState::<Direction>::default(),
-            ));
+        if let Some(gamepad) = gamepad {
+            for direction in Direction::ALL {
+                input_map.insert(direction, stick_direction(direction));
+            }
+            input_map.set_gamepad(gamepad);
         }
+
+        // In Bevy 0.16, insert InputMap and ActionState directly instead of using InputManagerBundle
+        entity.insert((input_map, ActionState::<Direction>::default()));
     }
 }
 
 fn handle_snake_direction(
     mut snakes: Query<&mut Snake>,
     mut proposed_direction: MessageReader<ProposeDirection>,
+    input_buffers: Res<InputBuffers>,
 ) {
     for proposed_direction in proposed_direction.read() {
+        let input_buffer = input_buffers.player(proposed_direction.id.0);
+
         for mut snake in snakes
             .iter_mut()
             .filter(|snake| snake.player_number == proposed_direction.id)
         {
-            // Don't accept more directions if queue is full (limit to 2)
-            if snake.next_directions.len() >= 2 {
-                return;
+            let mut next_directions = snake.next_directions.clone();
+
+            if next_directions.len() >= input_buffer.depth.max(1) {
+                match input_buffer.when_full {
+                    WhenFull::Drop => continue,
+                    WhenFull::OverwriteOldest => {
+                        next_directions.pop_front();
+                        // Without the oldest one, the next queued direction could reverse the snake
+                        while next_directions.front() == Some(&!snake.direction) {
+                            next_directions.pop_front();
+                        }
+                    }
+                }
             }
 
             // Validate against the effective current direction
             // (last queued direction, or current direction if queue is empty)
-            let effective_direction = snake
-                .next_directions
-                .back()
-                .unwrap_or(&snake.direction);
+            let effective_direction = *next_directions.back().unwrap_or(&snake.direction);
 
             // Don't allow reversing direction
-            if *effective_direction == !proposed_direction.direction {
-                return;
+            if effective_direction == !proposed_direction.direction {
+                continue;
             }
-            // Don't allow same direction
-            if *effective_direction == proposed_direction.direction {
-                return;
+            if effective_direction == proposed_direction.direction
+                && !input_buffer.allow_same_direction
+            {
+                continue;
             }
 
             // Add to queue
-            snake.next_directions.push_back(proposed_direction.direction);
+            next_directions.push_back(proposed_direction.direction);
+            snake.next_directions = next_directions;
         }
     }
 }
@@ -198,7 +195,7 @@ fn input_snake_direction(
     mut query: Query<(&mut Snake, &ActionState<Direction>)>,
     mut propose_direction: MessageWriter<ProposeDirection>,
 ) {
-    for (snake, direction) in query.iter_mut().filter(|(snake, _)| snake.next_directions.len() < 2) {
+    for (snake, direction) in query.iter_mut() {
         let direction = if direction.just_pressed(&Direction::Left) {
             Some(Direction::Left)
         } else if direction.just_pressed(&Direction::Right) {
@@ -219,3 +216,104 @@ fn input_snake_direction(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{controls::InputBuffer, headless::headless_app_with};
+
+    /// Player 1 starts going right and player 2 going left
+    fn app(input_buffer: InputBuffer) -> App {
+        headless_app_with(2, 0, |app| {
+            app.insert_resource(InputBuffers(vec![input_buffer; 2]));
+        })
+    }
+
+    fn propose(world: &mut World, directions: &[(u8, Direction)]) {
+        for &(player_number, direction) in directions {
+            world.write_message(ProposeDirection {
+                id: Id(player_number),
+                direction,
+            });
+        }
+        world.run_system_cached(handle_snake_direction).unwrap();
+    }
+
+    fn queue(world: &mut World, player_number: u8) -> Vec<Direction> {
+        let mut snakes = world.query::<&Snake>();
+        snakes
+            .iter(world)
+            .find(|snake| snake.player_number.0 == player_number)
+            .unwrap()
+            .next_directions
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn a_rejected_move_leaves_the_moves_of_other_players() {
+        let mut app = app(InputBuffer::default());
+        let world = app.world_mut();
+        propose(world, &[(1, Direction::Left), (2, Direction::Up)]);
+        assert!(queue(world, 1).is_empty());
+        assert_eq!(queue(world, 2), [Direction::Up]);
+    }
+
+    #[test]
+    fn a_full_queue_drops_later_moves() {
+        let mut app = app(InputBuffer {
+            depth: 2,
+            when_full: WhenFull::Drop,
+            ..default()
+        });
+        let world = app.world_mut();
+        propose(
+            world,
+            &[
+                (1, Direction::Up),
+                (1, Direction::Right),
+                (1, Direction::Down),
+            ],
+        );
+        assert_eq!(queue(world, 1), [Direction::Up, Direction::Right]);
+    }
+
+    #[test]
+    fn a_full_queue_can_overwrite_the_oldest_move() {
+        let mut app = app(InputBuffer {
+            depth: 2,
+            when_full: WhenFull::OverwriteOldest,
+            ..default()
+        });
+        let world = app.world_mut();
+        propose(
+            world,
+            &[
+                (1, Direction::Up),
+                (1, Direction::Right),
+                (1, Direction::Down),
+            ],
+        );
+        assert_eq!(queue(world, 1), [Direction::Right, Direction::Down]);
+
+        // Player 2 goes left, so without the oldest move turning right would reverse it
+        propose(world, &[(2, Direction::Up), (2, Direction::Right)]);
+        assert_eq!(queue(world, 2), [Direction::Up, Direction::Right]);
+        propose(world, &[(2, Direction::Down)]);
+        assert_eq!(queue(world, 2), [Direction::Down]);
+    }
+
+    #[test]
+    fn the_same_direction_is_only_queued_when_allowed() {
+        for allow_same_direction in [false, true] {
+            let mut app = app(InputBuffer {
+                allow_same_direction,
+                ..default()
+            });
+            let world = app.world_mut();
+            propose(world, &[(1, Direction::Right)]);
+            assert_eq!(queue(world, 1).is_empty(), !allow_same_direction);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Two instances of the game playing a match against each other through an in-memory link that delays and loses messages
+//! Latency is in ticks and loss is the probability of losing each message
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{
+    add_simulation_systems, rollback::rollback, Client, Link, NetEvent, NetMessage, NetMode, Role,
+    Session,
+};
+use crate::{
+    controls::InputBuffers,
+    direction::Direction,
+    headless::{headless_app_with, step},
+    movement::{ProposeDirection, TickCount},
+    replay::{add_recording_systems, recorded, simulate},
+    rules::GameRules,
+    snake::Id,
+    snapshot::{capture, state_hash},
+};
+
+/// Ticks played with random inputs
+const PLAYED_TICKS: u32 = 600;
+/// Steps after the inputs stop for every instance to catch up and every input to be confirmed
+const SETTLE_STEPS: u32 = 300;
+/// Probability of each instance pressing a direction on a given tick
+const PRESS_PROBABILITY: f64 = 0.2;
+/// Any seed works, a fixed one makes failures reproducible
+const SEED: u64 = 42;
+
+type Queue = Arc<Mutex<VecDeque<(u32, String)>>>;
+
+/// One end of an in-memory connection, messages arrive `latency` ticks after being sent
+struct LoopbackLink {
+    clock: Arc<AtomicU32>,
+    latency: u32,
+    loss: f64,
+    rng: ChaCha8Rng,
+    /// Messages in flight as RON, with the tick they arrive at
+    outgoing: Queue,
+    incoming: Queue,
+}
+
+impl LoopbackLink {
+    fn pair(clock: &Arc<AtomicU32>, latency: u32, loss: f64, seed: u64) -> (Self, Self) {
+        let (a, b) = (Queue::default(), Queue::default());
+        let end = |outgoing: &Queue, incoming: &Queue, seed| LoopbackLink {
+            clock: clock.clone(),
+            latency,
+            loss,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            outgoing: outgoing.clone(),
+            incoming: incoming.clone(),
+        };
+        (end(&a, &b, seed), end(&b, &a, seed.wrapping_add(1)))
+    }
+}
+
+impl Link for LoopbackLink {
+    fn send(&mut self, message: &NetMessage) {
+        // The lobby runs over TCP in the real game, only what the match resends can get lost
+        let can_get_lost = matches!(
+            message,
+            NetMessage::Inputs { .. } | NetMessage::Confirmed { .. } | NetMessage::StateHash { .. }
+        );
+        if can_get_lost && self.rng.gen_bool(self.loss) {
+            return;
+        }
+        let Ok(line) = ron::to_string(message) else {
+            return;
+        };
+        let arrival = self.clock.load(Ordering::Relaxed) + self.latency;
+        if let Ok(mut outgoing) = self.outgoing.lock() {
+            outgoing.push_back((arrival, line));
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Vec<NetMessage>> {
+        let now = self.clock.load(Ordering::Relaxed);
+        let mut incoming = self
+            .incoming
+            .lock()
+            .map_err(|_| io::Error::other("The other end panicked"))?;
+        let mut messages = Vec::new();
+        while incoming.front().is_some_and(|&(arrival, _)| arrival <= now) {
+            let Some((_, line)) = incoming.pop_front() else {
+                break;
+            };
+            messages.push(ron::from_str(&line).map_err(io::Error::other)?);
+        }
+        Ok(messages)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Plays a match with random inputs between a host and a client, both recording it
+/// Fails if the instances end up in different states
+fn play(latency: u32, loss: f64, mode: NetMode, seed: u64) -> Result<[App; 2], String> {
+    let clock = Arc::new(AtomicU32::new(0));
+    let (host_end, client_end) = LoopbackLink::pair(&clock, latency, loss, seed);
+    let mut host = Session::hosting(None, "Host".to_string());
+    if let Role::Host(host) = &mut host.role {
+        host.clients.push(Client {
+            link: Box::new(host_end),
+            player_number: None,
+            acknowledged: 0,
+        });
+    }
+    let mut client = Session::joining(Box::new(client_end), "Client".to_string());
+
+    // Lobby
+    let mut started = false;
+    for _ in 0..SETTLE_STEPS {
+        clock.fetch_add(1, Ordering::Relaxed);
+        ended(host.poll())?;
+        for event in client.poll() {
+            match event {
+                NetEvent::Start { mode, .. } => {
+                    client.begin_match(mode);
+                    started = true;
+                }
+                NetEvent::Ended(reason) => return Err(reason),
+            }
+        }
+        if started {
+            break;
+        }
+        if client.local_player != 0 && !host.started {
+            host.start(seed, &InputBuffers::default(), &GameRules::default(), mode);
+        }
+    }
+    if !started {
+        return Err("The client never joined".to_string());
+    }
+
+    let mut instances = [host, client].map(|session| {
+        headless_app_with(2, seed, |app| {
+            add_simulation_systems(app);
+            add_recording_systems(app);
+            app.insert_resource(session);
+        })
+    });
+    let mut inputs = ChaCha8Rng::seed_from_u64(seed);
+    for _ in 0..PLAYED_TICKS {
+        clock.fetch_add(1, Ordering::Relaxed);
+        for instance in instances.iter_mut() {
+            let world = instance.world_mut();
+            if inputs.gen_bool(PRESS_PROBABILITY) {
+                world.write_message(ProposeDirection {
+                    // The session moves the local player, whatever the id
+                    id: Id(1),
+                    direction: Direction::ALL[inputs.gen_range(0..Direction::ALL.len())],
+                });
+            }
+            poll(world)?;
+            step(world);
+        }
+    }
+
+    // Whoever fell behind catches up, then everything left gets confirmed
+    for _ in 0..SETTLE_STEPS {
+        clock.fetch_add(1, Ordering::Relaxed);
+        let newest = instances
+            .iter()
+            .map(|instance| instance.world().resource::<TickCount>().0)
+            .max()
+            .unwrap_or_default();
+        for instance in instances.iter_mut() {
+            let world = instance.world_mut();
+            poll(world)?;
+            if world.resource::<TickCount>().0 < newest {
+                step(world);
+            }
+        }
+    }
+
+    let [(host_tick, host_hash), (client_tick, client_hash)] =
+        instances.each_mut().map(|instance| {
+            let world = instance.world_mut();
+            (world.resource::<TickCount>().0, state_hash(&capture(world)))
+        });
+    let [host, client] = instances
+        .each_ref()
+        .map(|instance| instance.world().resource::<Session>());
+    if host_tick != client_tick {
+        return Err(format!(
+            "The host ended at tick {} and the client at tick {}",
+            host_tick, client_tick
+        ));
+    }
+    if host.next_confirmed < host_tick || client.next_confirmed < client_tick {
+        return Err("Not every input got confirmed".to_string());
+    }
+    let compared = host
+        .verified_hashes
+        .keys()
+        .filter(|tick| client.verified_hashes.contains_key(tick))
+        .count();
+    if host_hash != client_hash || compared == 0 {
+        return Err(format!("Desync by tick {}", host_tick));
+    }
+    Ok(instances)
+}
+
+fn rollbacks(instances: &[App; 2]) -> u32 {
+    instances
+        .iter()
+        .map(|instance| instance.world().resource::<Session>().rollback.count)
+        .sum()
+}
+
+fn ended(events: Vec<NetEvent>) -> Result<(), String> {
+    for event in events {
+        if let NetEvent::Ended(reason) = event {
+            return Err(reason);
+        }
+    }
+    Ok(())
+}
+
+/// What the network systems do every frame of the real game
+fn poll(world: &mut World) -> Result<(), String> {
+    ended(world.resource_mut::<Session>().poll())?;
+    if world.resource::<Session>().mode == NetMode::Rollback {
+        rollback(world);
+    }
+    Ok(())
+}
+
+#[test]
+fn lockstep_stays_in_sync_over_a_bad_connection() {
+    play(5, 0.2, NetMode::Lockstep, SEED).unwrap();
+}
+
+#[test]
+fn rollback_stays_in_sync_over_a_bad_connection() {
+    let instances = play(5, 0.2, NetMode::Rollback, SEED).unwrap();
+    assert!(rollbacks(&instances) > 0, "No prediction was ever wrong");
+}
+
+#[test]
+fn rollback_matches_replay_to_the_same_state() {
+    let mut instances = play(5, 0.2, NetMode::Rollback, SEED).unwrap();
+    assert!(rollbacks(&instances) > 0, "No prediction was ever wrong");
+    for instance in instances.iter_mut() {
+        let replay = recorded(instance.world());
+        let mut watched = simulate(&replay);
+        assert_eq!(
+            state_hash(&capture(watched.world_mut())),
+            state_hash(&capture(instance.world_mut()))
+        );
+    }
+}
@@ -0,0 +1,920 @@
+//! Playing over the local network
+//! Only the directions proposed for each tick are exchanged, every instance simulates the whole match from the same seed
+//! The host collects the inputs of every player and confirms them, see [`NetMode`] for what happens until then
+
+#[cfg(test)]
+mod loopback;
+mod peer;
+mod rollback;
+
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use bevy::{ecs::message::MessageCursor, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::{load_input_buffers, InputBuffers},
+    direction::Direction,
+    game_state::AppState,
+    main_menu::NumberOfPlayersSelected,
+    movement::{ProposeDirection, TickCount},
+    rng::Seed,
+    rules::{GameRules, LocalRules, SharedRules},
+    schedule::{tick_gate_open, TickGate},
+    snake::Id,
+    snapshot::{capture, state_hash, StartingSnapshot},
+    win::Won,
+    MAX_NUMBER_OF_PLAYERS,
+};
+use peer::{Link, Peer};
+use rollback::Rollback;
+
+const DEFAULT_PORT: u16 = 7777;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Ticks between pressing a direction and applying it in lockstep, so the input has time to reach everyone
+const INPUT_DELAY: u32 = 3;
+/// Ticks between comparing the state of the game across instances
+const HASH_INTERVAL: u32 = 10;
+/// Hashes kept around to compare with the ones other instances report
+const MAX_VERIFIED_HASHES: usize = 100;
+
+pub(crate) struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetSettings>()
+            .init_resource::<NetStatus>()
+            .add_systems(
+                Update,
+                (
+                    poll_network.run_if(resource_exists::<Session>),
+                    notice_winner.run_if(match_started),
+                ),
+            )
+            .add_systems(
+                PreUpdate,
+                rollback::rollback
+                    .run_if(in_state(AppState::InGame).and(playing(NetMode::Rollback))),
+            )
+            .add_systems(OnExit(AppState::InGame), leave_match.run_if(match_started))
+            .add_systems(
+                EguiPrimaryContextPass,
+                lobby_window.run_if(in_state(AppState::MainMenu)),
+            );
+        add_simulation_systems(app);
+    }
+}
+
+/// Systems that run along the ticks of a match, shared with the loopback tests
+fn add_simulation_systems(app: &mut App) {
+    app.add_systems(
+        FixedFirst,
+        (
+            exchange_inputs.run_if(playing(NetMode::Lockstep)),
+            rollback::predict_inputs.run_if(playing(NetMode::Rollback)),
+        )
+            .run_if(in_state(AppState::InGame)),
+    )
+    .add_systems(
+        FixedPostUpdate,
+        hash_state.run_if(
+            in_state(AppState::InGame)
+                .and(playing(NetMode::Lockstep))
+                .and(tick_gate_open),
+        ),
+    );
+}
+
+/// How a match deals with the time inputs take to reach everyone
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+enum NetMode {
+    /// Every tick waits for the inputs of every player, which are applied [`INPUT_DELAY`] ticks after being pressed
+    #[default]
+    Lockstep,
+    /// Local inputs apply right away and remote players are predicted to keep their direction
+    /// When a prediction turns out wrong, the ticks since then are simulated again
+    Rollback,
+}
+
+impl NetMode {
+    fn input_delay(self) -> u32 {
+        match self {
+            NetMode::Lockstep => INPUT_DELAY,
+            NetMode::Rollback => 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum NetMessage {
+    /// First message of a client
+    Hello {
+        name: String,
+    },
+    /// Player number of the client, it can change before the match starts
+    Welcome {
+        player_number: u8,
+    },
+    Refused {
+        reason: String,
+    },
+    /// Joined players and their names, by player number
+    Lobby {
+        players: BTreeMap<u8, String>,
+    },
+    /// Input buffers and rules have to match, since they change what the inputs do
+    Start {
+        seed: u64,
+        input_buffers: InputBuffers,
+        rules: GameRules,
+        mode: NetMode,
+    },
+    /// Directions a client proposed by tick, sent again until the host confirms them
+    Inputs {
+        /// Every confirmation before this tick arrived
+        acknowledged: u32,
+        inputs: Vec<(u32, Vec<Direction>)>,
+    },
+    /// Directions of every player by tick, in player order, sent again until the client acknowledges them
+    Confirmed {
+        inputs: Vec<(u32, Vec<(u8, Direction)>)>,
+    },
+    /// Hash of the state of the game when the tick count reached `tick`
+    StateHash {
+        tick: u32,
+        hash: u64,
+    },
+    Desync {
+        tick: u32,
+    },
+    Leave,
+}
+
+/// What the player typed in the lobby
+#[derive(Resource)]
+struct NetSettings {
+    name: String,
+    address: String,
+    mode: NetMode,
+}
+
+impl Default for NetSettings {
+    fn default() -> Self {
+        Self {
+            name: "Player".to_string(),
+            address: format!("127.0.0.1:{}", DEFAULT_PORT),
+            mode: NetMode::default(),
+        }
+    }
+}
+
+/// Why the last session ended, or why it couldn't start
+#[derive(Resource, Default)]
+struct NetStatus(Option<String>);
+
+/// Something that happened on the network that the game has to react to
+enum NetEvent {
+    Start {
+        seed: u64,
+        input_buffers: InputBuffers,
+        rules: GameRules,
+        mode: NetMode,
+    },
+    Ended(String),
+}
+
+/// A lobby or a match being played over the network
+#[derive(Resource)]
+struct Session {
+    role: Role,
+    mode: NetMode,
+    /// 0 until the host welcomes us
+    local_player: u8,
+    /// Joined players and their names, by player number
+    players: BTreeMap<u8, String>,
+    started: bool,
+    /// Someone won, so the match ends at the same tick for everyone
+    finished: bool,
+    /// Directions proposed locally that haven't been sent yet
+    local_inputs: Vec<Direction>,
+    /// Last tick the local inputs were sent for, in lockstep
+    sent_for: Option<u32>,
+    /// Local inputs by tick, until the host confirms them
+    unconfirmed: BTreeMap<u32, Vec<Direction>>,
+    /// Inputs of every player for the ticks that are confirmed but not simulated yet
+    confirmed: BTreeMap<u32, Vec<(u8, Direction)>>,
+    /// Every tick before this one is confirmed
+    next_confirmed: u32,
+    rollback: Rollback,
+    /// Hashes of the state of the game at the ticks every instance agrees on
+    verified_hashes: BTreeMap<u32, u64>,
+}
+
+enum Role {
+    Host(Host),
+    Client { host: Box<dyn Link> },
+}
+
+struct Host {
+    /// `None` when the clients are connected some other way, like in the loopback tests
+    listener: Option<TcpListener>,
+    clients: Vec<Client>,
+    /// Inputs received for ticks that aren't confirmed yet, by tick and player number
+    pending: BTreeMap<u32, BTreeMap<u8, Vec<Direction>>>,
+    /// Confirmed inputs by tick, until every client acknowledges them
+    sent: BTreeMap<u32, Vec<(u8, Direction)>>,
+    /// Hashes reported by the clients, as `(tick, player number, hash)`
+    remote_hashes: Vec<(u32, u8, u64)>,
+}
+
+struct Client {
+    link: Box<dyn Link>,
+    /// `None` until it says hello
+    player_number: Option<u8>,
+    /// Every confirmation before this tick arrived
+    acknowledged: u32,
+}
+
+impl Host {
+    fn broadcast(&mut self, message: &NetMessage) {
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.player_number.is_some())
+        {
+            client.link.send(message);
+        }
+    }
+
+    /// First tick where a client reported a different hash than ours
+    fn desynced_tick(&mut self, local_hashes: &BTreeMap<u32, u64>) -> Option<u32> {
+        let mut desynced_tick = None;
+        self.remote_hashes
+            .retain(|&(tick, _, hash)| match local_hashes.get(&tick) {
+                Some(&local_hash) => {
+                    if local_hash != hash {
+                        desynced_tick =
+                            Some(desynced_tick.map_or(tick, |earliest: u32| earliest.min(tick)));
+                    }
+                    false
+                }
+                // Keep it until we get to that tick
+                None => local_hashes
+                    .last_key_value()
+                    .is_none_or(|(&newest, _)| tick > newest),
+            });
+        desynced_tick
+    }
+}
+
+impl Session {
+    fn new(role: Role, local_player: u8, players: BTreeMap<u8, String>) -> Self {
+        Self {
+            role,
+            mode: NetMode::default(),
+            local_player,
+            players,
+            started: false,
+            finished: false,
+            local_inputs: Vec::new(),
+            sent_for: None,
+            unconfirmed: BTreeMap::new(),
+            confirmed: BTreeMap::new(),
+            next_confirmed: 0,
+            rollback: Rollback::default(),
+            verified_hashes: BTreeMap::new(),
+        }
+    }
+
+    fn host(name: String) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", DEFAULT_PORT))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::hosting(Some(listener), name))
+    }
+
+    fn hosting(listener: Option<TcpListener>, name: String) -> Self {
+        let host = Host {
+            listener,
+            clients: Vec::new(),
+            pending: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            remote_hashes: Vec::new(),
+        };
+        Self::new(Role::Host(host), 1, BTreeMap::from([(1, name)]))
+    }
+
+    fn join(address: &str, name: String) -> io::Result<Self> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "No address to join"))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        Ok(Self::joining(Box::new(Peer::new(stream)?), name))
+    }
+
+    fn joining(mut host: Box<dyn Link>, name: String) -> Self {
+        host.send(&NetMessage::Hello { name });
+        Self::new(Role::Client { host }, 0, BTreeMap::new())
+    }
+
+    fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host(_))
+    }
+
+    /// Host only, tells everyone to start a match
+    fn start(&mut self, seed: u64, input_buffers: &InputBuffers, rules: &GameRules, mode: NetMode) {
+        let Role::Host(host) = &mut self.role else {
+            return;
+        };
+
+        // Snakes are spawned for players 1 to n, so there can't be gaps left by players that left
+        let renumbered = self
+            .players
+            .keys()
+            .copied()
+            .zip(1..)
+            .collect::<BTreeMap<u8, u8>>();
+        self.players = std::mem::take(&mut self.players)
+            .into_values()
+            .zip(1..)
+            .map(|(name, player_number)| (player_number, name))
+            .collect();
+        for client in host.clients.iter_mut() {
+            let Some(player_number) = client.player_number else {
+                continue;
+            };
+            let player_number = renumbered[&player_number];
+            client.player_number = Some(player_number);
+            client.link.send(&NetMessage::Welcome { player_number });
+        }
+
+        host.broadcast(&NetMessage::Lobby {
+            players: self.players.clone(),
+        });
+        host.broadcast(&NetMessage::Start {
+            seed,
+            input_buffers: input_buffers.clone(),
+            rules: rules.clone(),
+            mode,
+        });
+        self.begin_match(mode);
+    }
+
+    fn begin_match(&mut self, mode: NetMode) {
+        self.mode = mode;
+        self.started = true;
+        self.finished = false;
+        self.local_inputs.clear();
+        self.sent_for = None;
+        self.unconfirmed.clear();
+        self.rollback = Rollback::default();
+        self.verified_hashes.clear();
+        // Nobody could press anything before the match started
+        let input_delay = mode.input_delay();
+        self.confirmed = (0..input_delay).map(|tick| (tick, Vec::new())).collect();
+        self.next_confirmed = input_delay;
+        if let Role::Host(host) = &mut self.role {
+            host.pending.clear();
+            host.sent.clear();
+            host.remote_hashes.clear();
+            for client in host.clients.iter_mut() {
+                client.acknowledged = input_delay;
+            }
+        }
+    }
+
+    /// Directions proposed locally to apply before `tick`
+    /// Clients send them on every poll, along with the ones that aren't confirmed yet
+    fn queue_local_inputs(&mut self, tick: u32, directions: Vec<Direction>) {
+        self.unconfirmed.insert(tick, directions.clone());
+        if let Role::Host(host) = &mut self.role {
+            host.pending
+                .entry(tick)
+                .or_default()
+                .insert(self.local_player, directions);
+            self.confirm_ready_ticks();
+        }
+    }
+
+    /// Host only, confirms the ticks everyone sent their inputs for, in order
+    fn confirm_ready_ticks(&mut self) {
+        let Role::Host(host) = &mut self.role else {
+            return;
+        };
+        while let Some(entry) = host.pending.first_entry() {
+            let ready = *entry.key() == self.next_confirmed
+                && self
+                    .players
+                    .keys()
+                    .all(|player_number| entry.get().contains_key(player_number));
+            if !ready {
+                break;
+            }
+            let (tick, inputs) = entry.remove_entry();
+            let inputs = inputs
+                .into_iter()
+                .flat_map(|(player_number, directions)| {
+                    directions
+                        .into_iter()
+                        .map(move |direction| (player_number, direction))
+                })
+                .collect::<Vec<_>>();
+            host.sent.insert(tick, inputs.clone());
+            self.confirmed.insert(tick, inputs);
+            self.next_confirmed = tick + 1;
+        }
+        let next_confirmed = self.next_confirmed;
+        self.unconfirmed.retain(|&tick, _| tick >= next_confirmed);
+    }
+
+    /// Client only, keeps the confirmations that weren't already received
+    fn receive_confirmed(&mut self, inputs: Vec<(u32, Vec<(u8, Direction)>)>) {
+        for (tick, inputs) in inputs {
+            if tick >= self.next_confirmed {
+                self.confirmed.insert(tick, inputs);
+            }
+        }
+        // They can arrive out of order when some get lost
+        while self.confirmed.contains_key(&self.next_confirmed) {
+            self.next_confirmed += 1;
+        }
+        let next_confirmed = self.next_confirmed;
+        self.unconfirmed.retain(|&tick, _| tick >= next_confirmed);
+    }
+
+    fn report_hash(&mut self, tick: u32, hash: u64) {
+        self.verified_hashes.insert(tick, hash);
+        if self.verified_hashes.len() > MAX_VERIFIED_HASHES {
+            self.verified_hashes.pop_first();
+        }
+        if let Role::Client { host } = &mut self.role {
+            host.send(&NetMessage::StateHash { tick, hash });
+        }
+    }
+
+    fn poll(&mut self) -> Vec<NetEvent> {
+        let events = if self.is_host() {
+            self.poll_host()
+        } else {
+            self.poll_client()
+        };
+        self.confirm_ready_ticks();
+        // Whatever got lost is sent again, until the other side says it arrived
+        if self.started && !self.finished {
+            self.send_confirmed();
+            self.send_unconfirmed();
+        }
+        self.flush();
+        events
+    }
+
+    fn poll_host(&mut self) -> Vec<NetEvent> {
+        let Role::Host(host) = &mut self.role else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+
+        if let Some(listener) = &host.listener {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => match Peer::new(stream) {
+                        Ok(peer) => host.clients.push(Client {
+                            link: Box::new(peer),
+                            player_number: None,
+                            acknowledged: 0,
+                        }),
+                        Err(error) => warn!("Could not accept a player: {}", error),
+                    },
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(error) => {
+                        warn!("Could not accept a player: {}", error);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut lobby_changed = false;
+        let mut disconnected = Vec::new();
+        for (index, client) in host.clients.iter_mut().enumerate() {
+            let Ok(messages) = client.link.receive() else {
+                disconnected.push(index);
+                continue;
+            };
+            for message in messages {
+                match (message, client.player_number) {
+                    (NetMessage::Hello { name }, None) => {
+                        let free_player_number = (1..=MAX_NUMBER_OF_PLAYERS as u8)
+                            .find(|player_number| !self.players.contains_key(player_number));
+                        let reason = match free_player_number {
+                            _ if self.started => "The match already started",
+                            None => "The lobby is full",
+                            Some(player_number) => {
+                                self.players.insert(player_number, name);
+                                client.player_number = Some(player_number);
+                                client.link.send(&NetMessage::Welcome { player_number });
+                                lobby_changed = true;
+                                continue;
+                            }
+                        };
+                        client.link.send(&NetMessage::Refused {
+                            reason: reason.to_string(),
+                        });
+                    }
+                    (
+                        NetMessage::Inputs {
+                            acknowledged,
+                            inputs,
+                        },
+                        Some(player_number),
+                    ) if self.started => {
+                        client.acknowledged = client.acknowledged.max(acknowledged);
+                        // Resent inputs for ticks that are already confirmed are ignored
+                        for (tick, directions) in inputs
+                            .into_iter()
+                            .filter(|&(tick, _)| tick >= self.next_confirmed)
+                        {
+                            host.pending
+                                .entry(tick)
+                                .or_default()
+                                .entry(player_number)
+                                .or_insert(directions);
+                        }
+                    }
+                    (NetMessage::StateHash { tick, hash }, Some(player_number)) => {
+                        host.remote_hashes.push((tick, player_number, hash));
+                    }
+                    (NetMessage::Leave, _) => {
+                        disconnected.push(index);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for index in disconnected.into_iter().rev() {
+            let client = host.clients.remove(index);
+            let Some(player_number) = client.player_number else {
+                continue;
+            };
+            let name = self.players.remove(&player_number).unwrap_or_default();
+            if self.started {
+                events.push(NetEvent::Ended(format!("{} left the match", name)));
+            } else {
+                lobby_changed = true;
+            }
+        }
+        if lobby_changed {
+            host.broadcast(&NetMessage::Lobby {
+                players: self.players.clone(),
+            });
+        }
+
+        if let Some(tick) = host.desynced_tick(&self.verified_hashes) {
+            host.broadcast(&NetMessage::Desync { tick });
+            events.push(NetEvent::Ended(format!("Desync at tick {}", tick)));
+        }
+        events
+    }
+
+    /// Host only, sends every client the confirmations it hasn't acknowledged yet
+    fn send_confirmed(&mut self) {
+        let Role::Host(host) = &mut self.role else {
+            return;
+        };
+        let mut acknowledged_by_everyone = self.next_confirmed;
+        for client in host.clients.iter_mut() {
+            if client.player_number.is_none() {
+                continue;
+            }
+            acknowledged_by_everyone = acknowledged_by_everyone.min(client.acknowledged);
+            if client.acknowledged < self.next_confirmed {
+                let inputs = host
+                    .sent
+                    .range(client.acknowledged..)
+                    .map(|(&tick, inputs)| (tick, inputs.clone()))
+                    .collect();
+                client.link.send(&NetMessage::Confirmed { inputs });
+            }
+        }
+        host.sent = host.sent.split_off(&acknowledged_by_everyone);
+    }
+
+    /// Client only, sends the local inputs the host hasn't confirmed yet
+    fn send_unconfirmed(&mut self) {
+        let Role::Client { host } = &mut self.role else {
+            return;
+        };
+        host.send(&NetMessage::Inputs {
+            acknowledged: self.next_confirmed,
+            inputs: self
+                .unconfirmed
+                .iter()
+                .map(|(&tick, directions)| (tick, directions.clone()))
+                .collect(),
+        });
+    }
+
+    fn flush(&mut self) {
+        // A broken connection is noticed when receiving
+        match &mut self.role {
+            Role::Host(host) => {
+                for client in host.clients.iter_mut() {
+                    let _ = client.link.flush();
+                }
+            }
+            Role::Client { host } => {
+                let _ = host.flush();
+            }
+        }
+    }
+
+    fn poll_client(&mut self) -> Vec<NetEvent> {
+        let Role::Client { host } = &mut self.role else {
+            return Vec::new();
+        };
+        let Ok(messages) = host.receive() else {
+            return vec![NetEvent::Ended(
+                "Lost the connection to the host".to_string(),
+            )];
+        };
+
+        let mut events = Vec::new();
+        for message in messages {
+            match message {
+                NetMessage::Welcome { player_number } => self.local_player = player_number,
+                NetMessage::Refused { reason } => events.push(NetEvent::Ended(reason)),
+                NetMessage::Lobby { players } => self.players = players,
+                NetMessage::Start {
+                    seed,
+                    input_buffers,
+                    rules,
+                    mode,
+                } => events.push(NetEvent::Start {
+                    seed,
+                    input_buffers,
+                    rules,
+                    mode,
+                }),
+                NetMessage::Confirmed { inputs } if self.started => self.receive_confirmed(inputs),
+                NetMessage::Desync { tick } => {
+                    events.push(NetEvent::Ended(format!("Desync at tick {}", tick)))
+                }
+                NetMessage::Leave => events.push(NetEvent::Ended("The host left".to_string())),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Tells everyone we are gone
+    fn leave(&mut self) {
+        match &mut self.role {
+            Role::Host(host) => host.broadcast(&NetMessage::Leave),
+            Role::Client { host } => host.send(&NetMessage::Leave),
+        }
+    }
+}
+
+fn match_started(session: Option<Res<Session>>) -> bool {
+    session.is_some_and(|session| session.started)
+}
+
+fn playing(mode: NetMode) -> impl Fn(Option<Res<Session>>) -> bool {
+    move |session| session.is_some_and(|session| session.started && session.mode == mode)
+}
+
+fn close_session(commands: &mut Commands) {
+    commands.queue(|world: &mut World| {
+        if let Some(mut session) = world.remove_resource::<Session>() {
+            session.leave();
+        }
+        world.resource_mut::<TickGate>().0 = true;
+        // The host might have sent different ones
+        let _ = world.run_system_cached(load_input_buffers);
+        world.remove_resource::<SharedRules>();
+        let rules = world.resource::<LocalRules>().0.clone();
+        world.insert_resource(rules);
+    });
+}
+
+fn poll_network(
+    mut commands: Commands,
+    mut session: ResMut<Session>,
+    mut status: ResMut<NetStatus>,
+    mut seed: ResMut<Seed>,
+    mut number_of_players: ResMut<NumberOfPlayersSelected>,
+    app_state: Res<State<AppState>>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    for event in session.poll() {
+        match event {
+            NetEvent::Start {
+                seed: match_seed,
+                input_buffers,
+                rules,
+                mode,
+            } => {
+                session.begin_match(mode);
+                seed.0 = match_seed;
+                number_of_players.0 = session.players.len();
+                commands.insert_resource(input_buffers);
+                commands.insert_resource(rules);
+                commands.insert_resource(SharedRules);
+                commands.insert_resource(StartingSnapshot::default());
+                app_state_next_state.set(AppState::InGame);
+                status.0 = None;
+            }
+            NetEvent::Ended(reason) => {
+                status.0 = Some(reason);
+                close_session(&mut commands);
+                if *app_state.get() != AppState::MainMenu {
+                    app_state_next_state.set(AppState::MainMenu);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Sends what was pressed locally and only lets the next tick run once its inputs are confirmed
+fn exchange_inputs(
+    mut session: ResMut<Session>,
+    mut proposed_direction: ResMut<Messages<ProposeDirection>>,
+    mut local_cursor: Local<MessageCursor<ProposeDirection>>,
+    tick_count: Res<TickCount>,
+    mut tick_gate: ResMut<TickGate>,
+) {
+    // Whatever was pressed locally, with any bindings, moves the local player
+    let local_directions = take_local_directions(&mut local_cursor, &mut proposed_direction);
+    session.local_inputs.extend(local_directions);
+
+    let tick = tick_count.0;
+    let input_tick = tick + INPUT_DELAY;
+    if session
+        .sent_for
+        .is_none_or(|sent_for| sent_for < input_tick)
+    {
+        let directions = std::mem::take(&mut session.local_inputs);
+        session.sent_for = Some(input_tick);
+        session.queue_local_inputs(input_tick, directions);
+    }
+
+    let Some(inputs) = session.confirmed.remove(&tick) else {
+        tick_gate.0 = false;
+        return;
+    };
+    tick_gate.0 = true;
+    for (player_number, direction) in inputs {
+        proposed_direction.write(ProposeDirection {
+            id: Id(player_number),
+            direction,
+        });
+    }
+    local_cursor.clear(&proposed_direction);
+}
+
+/// Takes the directions proposed since the last tick, so only the ones the session proposes move the snakes
+/// The ones the session proposed itself stay until the messages update, `cursor` has to skip past them
+fn take_local_directions(
+    cursor: &mut MessageCursor<ProposeDirection>,
+    proposed_direction: &mut Messages<ProposeDirection>,
+) -> Vec<Direction> {
+    let directions = cursor
+        .read(proposed_direction)
+        .map(|proposed| proposed.direction)
+        .collect();
+    proposed_direction.clear();
+    directions
+}
+
+fn hash_state(world: &mut World) {
+    let tick = world.resource::<TickCount>().0;
+    if !tick.is_multiple_of(HASH_INTERVAL) {
+        return;
+    }
+    let hash = state_hash(&capture(world));
+    world.resource_mut::<Session>().report_hash(tick, hash);
+}
+
+fn notice_winner(mut won: MessageReader<Won>, mut session: ResMut<Session>) {
+    if won.read().count() > 0 {
+        session.finished = true;
+    }
+}
+
+/// After a win everyone goes back to the lobby, leaving in the middle of a match ends the session
+fn leave_match(
+    mut commands: Commands,
+    mut session: ResMut<Session>,
+    mut tick_gate: ResMut<TickGate>,
+) {
+    if session.mode == NetMode::Rollback {
+        info!(
+            "Wrong predictions were fixed {} times",
+            session.rollback.count
+        );
+    }
+    if !session.finished {
+        close_session(&mut commands);
+        return;
+    }
+    session.started = false;
+    session.finished = false;
+    tick_gate.0 = true;
+    commands.run_system_cached(load_input_buffers);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn lobby_window(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    session: Option<ResMut<Session>>,
+    mut settings: ResMut<NetSettings>,
+    mut status: ResMut<NetStatus>,
+    seed: Res<Seed>,
+    input_buffers: Res<InputBuffers>,
+    rules: Res<GameRules>,
+    mut number_of_players: ResMut<NumberOfPlayersSelected>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("LAN").show(ctx, |ui| {
+        if let Some(status) = &status.0 {
+            ui.colored_label(egui::Color32::LIGHT_RED, status);
+        }
+
+        let Some(mut session) = session else {
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut settings.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Host address");
+                ui.text_edit_singleline(&mut settings.address);
+            });
+            let mut result = None;
+            ui.horizontal(|ui| {
+                if ui
+                    .button(format!("Host on port {}", DEFAULT_PORT))
+                    .clicked()
+                {
+                    result = Some(Session::host(settings.name.clone()));
+                }
+                if ui.button("Join").clicked() {
+                    result = Some(Session::join(&settings.address, settings.name.clone()));
+                }
+            });
+            match result {
+                Some(Ok(session)) => {
+                    commands.insert_resource(session);
+                    status.0 = None;
+                }
+                Some(Err(error)) => status.0 = Some(error.to_string()),
+                None => {}
+            }
+            return;
+        };
+
+        egui::Grid::new("lobby").striped(true).show(ui, |ui| {
+            for (player_number, name) in session.players.iter() {
+                ui.label(format!("Player {}", player_number));
+                ui.label(name);
+                if *player_number == session.local_player {
+                    ui.label("(you)");
+                }
+                ui.end_row();
+            }
+        });
+
+        if session.is_host() {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut settings.mode, NetMode::Lockstep, "Lockstep");
+                ui.radio_value(&mut settings.mode, NetMode::Rollback, "Rollback");
+            });
+        }
+        ui.horizontal(|ui| {
+            if !session.is_host() {
+                ui.label("Waiting for the host to start");
+            } else if ui.button("Start").clicked() {
+                session.start(seed.0, &input_buffers, &rules, settings.mode);
+                number_of_players.0 = session.players.len();
+                commands.insert_resource(StartingSnapshot::default());
+                // The clients got these, they can't change until the session is over
+                commands.insert_resource(SharedRules);
+                app_state_next_state.set(AppState::InGame);
+                status.0 = None;
+            }
+            if ui.button("Leave").clicked() {
+                close_session(&mut commands);
+            }
+        });
+    });
+}
@@ -0,0 +1,93 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use bevy::log::warn;
+
+use super::NetMessage;
+
+/// A connection to another instance of the game
+/// The match only needs messages to arrive eventually, the protocol resends what gets lost
+pub(super) trait Link: Send + Sync {
+    fn send(&mut self, message: &NetMessage);
+
+    /// Every message that arrived since the last call
+    /// Fails once the connection is closed
+    fn receive(&mut self) -> io::Result<Vec<NetMessage>>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// The other end of a TCP connection, exchanging one RON value per line
+/// Never blocks, reads and writes are buffered until the socket is ready
+pub(super) struct Peer {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl Peer {
+    pub(super) fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        // Inputs are tiny and latency is what matters
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+}
+
+impl Link for Peer {
+    fn send(&mut self, message: &NetMessage) {
+        match ron::to_string(message) {
+            Ok(line) => {
+                self.outgoing.extend_from_slice(line.as_bytes());
+                self.outgoing.push(b'\n');
+            }
+            Err(error) => warn!("Could not serialize network message: {}", error),
+        }
+        // Errors show up again on the next receive, where they can be handled
+        let _ = self.flush();
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Vec<NetMessage>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|&byte| byte == b'\n') {
+            let line = self.incoming.drain(..=end).collect::<Vec<_>>();
+            let message = std::str::from_utf8(&line)
+                .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+                .and_then(|line| {
+                    ron::from_str(line)
+                        .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+                })?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
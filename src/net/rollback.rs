@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use bevy::{ecs::message::MessageCursor, prelude::*};
+
+use super::{take_local_directions, Session, HASH_INTERVAL};
+use crate::{
+    apple::AppleEaten,
+    collision::{Collision, RemoveChunks},
+    direction::Direction,
+    movement::{ProposeDirection, TickCount},
+    replay::forget_inputs_since,
+    schedule::{run_tick, TickGate},
+    snake::Id,
+    snapshot::{capture, restore, state_hash, GameSnapshot},
+    win::{CurrentFirst, WinnerHoldTimer},
+};
+
+/// How far the game can run ahead of the confirmed inputs before waiting for them
+const MAX_PREDICTED_TICKS: u32 = 20;
+
+/// The ticks simulated with predicted inputs, to go back to them when the real ones arrive
+#[derive(Default)]
+pub(super) struct Rollback {
+    /// The state before each tick and the inputs it was simulated with
+    history: BTreeMap<u32, (GameSnapshot, Vec<(u8, Direction)>)>,
+    resimulating: bool,
+    /// How many times a wrong prediction had to be fixed
+    pub(super) count: u32,
+}
+
+impl Rollback {
+    /// First tick that was simulated with different inputs than the confirmed ones
+    fn mispredicted(&self, confirmed: &BTreeMap<u32, Vec<(u8, Direction)>>) -> Option<u32> {
+        self.history
+            .iter()
+            .find(|(tick, (_, inputs))| confirmed.get(tick).is_some_and(|real| real != inputs))
+            .map(|(&tick, _)| tick)
+    }
+}
+
+/// Proposes the inputs for the tick that is about to run, predicting the ones that didn't arrive yet
+/// Remote players are predicted to keep their direction, which is what a snake does without inputs
+pub(super) fn predict_inputs(
+    world: &mut World,
+    mut local_cursor: Local<MessageCursor<ProposeDirection>>,
+) {
+    let tick = world.resource::<TickCount>().0;
+    let local_directions = take_local_directions(
+        &mut local_cursor,
+        &mut world.resource_mut::<Messages<ProposeDirection>>(),
+    );
+    let may_win = world.resource::<CurrentFirst>().0.is_some()
+        && world.resource::<WinnerHoldTimer>().0.remaining()
+            <= world.resource::<Time<Fixed>>().timestep();
+
+    let mut session = world.resource_mut::<Session>();
+    if !session.rollback.resimulating {
+        // Whatever was pressed locally, with any bindings, moves the local player
+        session.local_inputs.extend(local_directions);
+        if tick >= session.next_confirmed && !session.unconfirmed.contains_key(&tick) {
+            let directions = std::mem::take(&mut session.local_inputs);
+            session.queue_local_inputs(tick, directions);
+        }
+
+        // A win can't be taken back, so the tick that might end the match waits for the real inputs
+        let settled = tick < session.next_confirmed
+            && session.rollback.mispredicted(&session.confirmed).is_none();
+        if tick >= session.next_confirmed + MAX_PREDICTED_TICKS || (may_win && !settled) {
+            world.resource_mut::<TickGate>().0 = false;
+            return;
+        }
+    }
+
+    let inputs = match session.confirmed.get(&tick) {
+        Some(inputs) => inputs.clone(),
+        None => session
+            .unconfirmed
+            .get(&tick)
+            .into_iter()
+            .flatten()
+            .map(|&direction| (session.local_player, direction))
+            .collect(),
+    };
+    let snapshot = capture(world);
+    world
+        .resource_mut::<Session>()
+        .rollback
+        .history
+        .insert(tick, (snapshot, inputs.clone()));
+
+    world.resource_mut::<TickGate>().0 = true;
+    for (player_number, direction) in inputs {
+        world.write_message(ProposeDirection {
+            id: Id(player_number),
+            direction,
+        });
+    }
+    local_cursor.clear(world.resource::<Messages<ProposeDirection>>());
+}
+
+/// Goes back to the first tick simulated with a wrong prediction and simulates every tick since then again
+pub(super) fn rollback(world: &mut World) {
+    let present = world.resource::<TickCount>().0;
+    let mispredicted = {
+        let session = world.resource::<Session>();
+        session
+            .rollback
+            .mispredicted(&session.confirmed)
+            .and_then(|tick| session.rollback.history.get(&tick))
+            .map(|(snapshot, _)| snapshot.clone())
+    };
+
+    if let Some(snapshot) = mispredicted {
+        restore(world, &snapshot);
+        // They get recorded again, with the real inputs this time
+        forget_inputs_since(world, snapshot.tick);
+        let mut session = world.resource_mut::<Session>();
+        session.rollback.resimulating = true;
+        session.rollback.count += 1;
+        for _ in snapshot.tick..present {
+            run_tick(world);
+        }
+        world.resource_mut::<Session>().rollback.resimulating = false;
+        // They were already shown when the ticks ran the first time
+        world.resource_mut::<Messages<AppleEaten>>().clear();
+        world.resource_mut::<Messages<Collision>>().clear();
+        world.resource_mut::<Messages<RemoveChunks>>().clear();
+    }
+
+    forget_settled(world);
+}
+
+/// Drops what can't be rolled back anymore, hashing the states every instance agrees on
+fn forget_settled(world: &mut World) {
+    let present = world.resource::<TickCount>().0;
+    let mut session = world.resource_mut::<Session>();
+    // The state before a confirmed tick only depends on confirmed ticks
+    let settled = session.next_confirmed.min(present);
+
+    let mut hashes = Vec::new();
+    while let Some(entry) = session.rollback.history.first_entry() {
+        if *entry.key() >= settled {
+            break;
+        }
+        let (tick, (snapshot, _)) = entry.remove_entry();
+        if tick % HASH_INTERVAL == 0 {
+            hashes.push((tick, state_hash(&snapshot)));
+        }
+    }
+    session.confirmed.retain(|&tick, _| tick >= settled);
+
+    for (tick, hash) in hashes {
+        session.report_hash(tick, hash);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apple::AppleEaten, collision::Collision, game_state::AppState,
+    main_menu::NumberOfPlayersSelected, snake::Snake, storage::Storage, win::Won,
+};
+
+const RECORDS_KEY: &str = "records";
+const MAX_MATCH_HISTORY: usize = 50;
+
+pub(crate) struct RecordsPlugin;
+
+impl Plugin for RecordsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Records>()
+            .init_resource::<CurrentMatch>()
+            .add_systems(Startup, load_records)
+            .add_systems(OnEnter(AppState::InGame), start_match)
+            .add_systems(
+                Update,
+                (count_apples, count_collisions, track_lengths, track_winner)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), finish_match)
+            .add_systems(
+                EguiPrimaryContextPass,
+                records_window.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// Everything that is remembered between runs of the game
+#[derive(Resource, Serialize, Deserialize, Default)]
+struct Records {
+    /// Longest snake ever seen in each mode
+    high_scores: BTreeMap<String, HighScore>,
+    /// Lifetime statistics by player name
+    players: BTreeMap<String, PlayerStats>,
+    /// Most recent matches last
+    history: Vec<MatchRecord>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct HighScore {
+    name: String,
+    length: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PlayerStats {
+    matches: u32,
+    wins: u32,
+    apples: u32,
+    collisions: u32,
+    longest_snake: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MatchRecord {
+    mode: String,
+    winner: Option<String>,
+    /// Final length of each snake, longest first
+    players: Vec<(String, usize)>,
+    duration_seconds: f32,
+}
+
+/// Statistics of the match being played, by player name
+#[derive(Resource, Default)]
+struct CurrentMatch {
+    started_at: f32,
+    winner: Option<String>,
+    apples: HashMap<String, u32>,
+    collisions: HashMap<String, u32>,
+    longest_snake: HashMap<String, usize>,
+}
+
+impl CurrentMatch {
+    fn is_empty(&self) -> bool {
+        self.winner.is_none() && self.apples.is_empty() && self.collisions.is_empty()
+    }
+}
+
+fn mode(number_of_players: usize) -> String {
+    match number_of_players {
+        1 => "1 player".to_string(),
+        n => format!("{} players", n),
+    }
+}
+
+fn load_records(mut records: ResMut<Records>, storage: Res<Storage>) {
+    if let Some(stored) = storage.load(RECORDS_KEY) {
+        *records = stored;
+    }
+}
+
+fn start_match(mut current_match: ResMut<CurrentMatch>, time: Res<Time>) {
+    *current_match = CurrentMatch {
+        started_at: time.elapsed_secs(),
+        ..default()
+    };
+}
+
+fn count_apples(
+    mut apple_eaten: MessageReader<AppleEaten>,
+    snakes: Query<&Snake>,
+    mut current_match: ResMut<CurrentMatch>,
+) {
+    for AppleEaten(entity) in apple_eaten.read() {
+        if let Ok(snake) = snakes.get(*entity) {
+            *current_match.apples.entry(snake.name.clone()).or_default() += 1;
+        }
+    }
+}
+
+fn count_collisions(
+    mut collision: MessageReader<Collision>,
+    snakes: Query<&Snake>,
+    mut current_match: ResMut<CurrentMatch>,
+) {
+    for Collision(entity) in collision.read() {
+        if let Ok(snake) = snakes.get(*entity) {
+            *current_match
+                .collisions
+                .entry(snake.name.clone())
+                .or_default() += 1;
+        }
+    }
+}
+
+fn track_lengths(snakes: Query<&Snake, Changed<Snake>>, mut current_match: ResMut<CurrentMatch>) {
+    for snake in snakes.iter() {
+        let longest = current_match
+            .longest_snake
+            .entry(snake.name.clone())
+            .or_default();
+        *longest = (*longest).max(snake.segments.len());
+    }
+}
+
+fn track_winner(mut won: MessageReader<Won>, mut current_match: ResMut<CurrentMatch>) {
+    for Won(name) in won.read() {
+        current_match.winner = Some(name.clone());
+    }
+}
+
+fn finish_match(
+    snakes: Query<&Snake>,
+    current_match: Res<CurrentMatch>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    mut records: ResMut<Records>,
+    storage: Res<Storage>,
+    time: Res<Time>,
+) {
+    if current_match.is_empty() {
+        return;
+    }
+
+    let mode = mode(number_of_players.0);
+
+    let mut players = snakes
+        .iter()
+        .map(|snake| (snake.name.clone(), snake.segments.len()))
+        .collect::<Vec<_>>();
+    players.sort_by_key(|(_, length)| std::cmp::Reverse(*length));
+
+    for (name, _) in players.iter() {
+        let longest_snake = current_match
+            .longest_snake
+            .get(name)
+            .copied()
+            .unwrap_or_default();
+
+        let stats = records.players.entry(name.clone()).or_default();
+        stats.matches += 1;
+        stats.apples += current_match.apples.get(name).copied().unwrap_or_default();
+        stats.collisions += current_match
+            .collisions
+            .get(name)
+            .copied()
+            .unwrap_or_default();
+        stats.longest_snake = stats.longest_snake.max(longest_snake);
+        if current_match.winner.as_ref() == Some(name) {
+            stats.wins += 1;
+        }
+
+        let beats_high_score = records
+            .high_scores
+            .get(&mode)
+            .is_none_or(|high_score| longest_snake > high_score.length);
+        if beats_high_score {
+            records.high_scores.insert(
+                mode.clone(),
+                HighScore {
+                    name: name.clone(),
+                    length: longest_snake,
+                },
+            );
+        }
+    }
+
+    records.history.push(MatchRecord {
+        mode,
+        winner: current_match.winner.clone(),
+        players,
+        duration_seconds: time.elapsed_secs() - current_match.started_at,
+    });
+    let overflow = records.history.len().saturating_sub(MAX_MATCH_HISTORY);
+    records.history.drain(..overflow);
+
+    storage.save(RECORDS_KEY, &*records);
+}
+
+fn records_window(mut contexts: EguiContexts, records: Res<Records>) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Records").show(ctx, |ui| {
+        ui.collapsing("High scores", |ui| {
+            egui::Grid::new("high_scores").striped(true).show(ui, |ui| {
+                ui.strong("Mode");
+                ui.strong("Player");
+                ui.strong("Length");
+                ui.end_row();
+                for (mode, high_score) in records.high_scores.iter() {
+                    ui.label(mode);
+                    ui.label(&high_score.name);
+                    ui.label(high_score.length.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+
+        ui.collapsing("Players", |ui| {
+            egui::Grid::new("player_stats")
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in [
+                        "Player",
+                        "Matches",
+                        "Wins",
+                        "Apples",
+                        "Collisions",
+                        "Longest",
+                    ] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for (name, stats) in records.players.iter() {
+                        ui.label(name);
+                        ui.label(stats.matches.to_string());
+                        ui.label(stats.wins.to_string());
+                        ui.label(stats.apples.to_string());
+                        ui.label(stats.collisions.to_string());
+                        ui.label(stats.longest_snake.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+
+        ui.collapsing("Match history", |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("match_history")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for header in ["Mode", "Winner", "Players", "Duration"] {
+                                ui.strong(header);
+                            }
+                            ui.end_row();
+                            for record in records.history.iter().rev() {
+                                ui.label(&record.mode);
+                                ui.label(record.winner.as_deref().unwrap_or("-"));
+                                ui.label(
+                                    record
+                                        .players
+                                        .iter()
+                                        .map(|(name, length)| format!("{} {}", name, length))
+                                        .collect::<Vec<_>>()
+                                        .join(", "),
+                                );
+                                ui.label(format!("{:.0}s", record.duration_seconds));
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+    });
+}
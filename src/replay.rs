@@ -0,0 +1,375 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apple::AppleEaten,
+    collision::{Collision, RemoveChunks},
+    controls::{load_input_buffers, InputBuffers},
+    direction::Direction,
+    game_state::{AppState, InMatch},
+    main_menu::NumberOfPlayersSelected,
+    movement::{ProposeDirection, TickCount},
+    rng::Seed,
+    rules::{GameRules, LocalRules, SharedRules},
+    schedule::run_tick,
+    snake::Id,
+    snapshot::{GameSnapshot, SnapshotRestored, StartingSnapshot},
+    storage::Storage,
+    win::Won,
+};
+
+pub(crate) const REPLAYS_DIRECTORY: &str = "replays";
+
+pub(crate) struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        add_recording_systems(app);
+        app.init_resource::<StoredReplays>()
+            .add_systems(OnExit(AppState::InGame), save_recording)
+            // Picking a replay
+            .add_systems(OnEnter(AppState::MainMenu), list_replays)
+            .add_systems(
+                EguiPrimaryContextPass,
+                replays_window.run_if(in_state(AppState::MainMenu)),
+            )
+            // Playback
+            .add_systems(
+                FixedPreUpdate,
+                feed_inputs.run_if(in_state(AppState::Replay)),
+            )
+            .add_systems(
+                Update,
+                (seek, stop_at_end)
+                    .chain()
+                    .run_if(in_state(AppState::Replay)),
+            )
+            .add_systems(
+                EguiPrimaryContextPass,
+                playback_controls.run_if(in_state(AppState::Replay)),
+            )
+            .add_systems(
+                OnExit(AppState::Replay),
+                (
+                    reset_time,
+                    forget_starting_snapshot,
+                    load_input_buffers,
+                    restore_rules,
+                ),
+            );
+    }
+}
+
+/// Everything needed to simulate a match again
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct Replay {
+    pub(crate) seed: u64,
+    pub(crate) number_of_players: usize,
+    /// Board the match started from, when it wasn't the default one
+    #[serde(default)]
+    pub(crate) start: Option<GameSnapshot>,
+    /// How the inputs were queued, since it changes what they do
+    #[serde(default)]
+    pub(crate) input_buffers: InputBuffers,
+    /// Replays from before the rules could change were played with the default ones
+    #[serde(default)]
+    pub(crate) rules: GameRules,
+    /// Length of the match
+    pub(crate) ticks: u32,
+    /// Directions proposed before each tick, as `(tick, player number, direction)`
+    pub(crate) inputs: Vec<(u32, u8, Direction)>,
+}
+
+#[derive(Resource, Default)]
+struct Recording(Replay);
+
+/// Records every match played in game, the [`ReplayPlugin`] saves them
+pub(crate) fn add_recording_systems(app: &mut App) {
+    app.init_resource::<Recording>()
+        // Also when a match starts over, for example with the rules that just loaded
+        .add_systems(
+            OnEnter(InMatch),
+            start_recording.run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            FixedPreUpdate,
+            (restart_recording, record_inputs)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+}
+
+/// The match recorded so far
+pub(crate) fn recorded(world: &World) -> Replay {
+    Replay {
+        ticks: world.resource::<TickCount>().0,
+        ..world.resource::<Recording>().0.clone()
+    }
+}
+
+/// Forgets the inputs recorded from `tick` on, for when those ticks are simulated again
+pub(crate) fn forget_inputs_since(world: &mut World, tick: u32) {
+    if let Some(mut recording) = world.get_resource_mut::<Recording>() {
+        recording
+            .0
+            .inputs
+            .retain(|&(input_tick, _, _)| input_tick < tick);
+    }
+}
+
+fn start_recording(
+    mut recording: ResMut<Recording>,
+    seed: Res<Seed>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    starting_snapshot: Res<StartingSnapshot>,
+    input_buffers: Res<InputBuffers>,
+    rules: Res<GameRules>,
+) {
+    recording.0 = Replay {
+        seed: seed.0,
+        number_of_players: number_of_players.0,
+        start: starting_snapshot.0.clone(),
+        input_buffers: input_buffers.clone(),
+        rules: rules.clone(),
+        ..default()
+    };
+}
+
+/// After jumping to a snapshot the inputs so far are meaningless, so the replay starts from there
+fn restart_recording(
+    mut recording: ResMut<Recording>,
+    mut snapshot_restored: MessageReader<SnapshotRestored>,
+) {
+    for SnapshotRestored(snapshot) in snapshot_restored.read() {
+        recording.0.start = Some(snapshot.clone());
+        recording.0.inputs.clear();
+    }
+}
+
+// All the player and AI input goes through `ProposeDirection`, so that's all we need to store
+fn record_inputs(
+    mut recording: ResMut<Recording>,
+    mut proposed_direction: MessageReader<ProposeDirection>,
+    tick_count: Res<TickCount>,
+) {
+    for ProposeDirection { id, direction } in proposed_direction.read() {
+        recording.0.inputs.push((tick_count.0, id.0, *direction));
+    }
+}
+
+fn save_recording(world: &mut World) {
+    let replay = recorded(world);
+    if replay.inputs.is_empty() {
+        return;
+    }
+
+    world.resource::<Storage>().save(
+        &format!("{}/{:016x}", REPLAYS_DIRECTORY, replay.seed),
+        &replay,
+    );
+}
+
+#[derive(Resource, Default)]
+struct StoredReplays(Vec<String>);
+
+fn list_replays(mut stored_replays: ResMut<StoredReplays>, storage: Res<Storage>) {
+    stored_replays.0 = storage.list(REPLAYS_DIRECTORY);
+}
+
+fn replays_window(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    stored_replays: Res<StoredReplays>,
+    storage: Res<Storage>,
+    mut seed: ResMut<Seed>,
+    mut number_of_players: ResMut<NumberOfPlayersSelected>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Replays").show(ctx, |ui| {
+        if stored_replays.0.is_empty() {
+            ui.label("Play a match to record a replay");
+        }
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for key in stored_replays.0.iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(
+                            key.trim_start_matches(REPLAYS_DIRECTORY)
+                                .trim_start_matches('/'),
+                        );
+                        if !ui.button("Watch").clicked() {
+                            return;
+                        }
+                        let Some(replay) = storage.load::<Replay>(key) else {
+                            return;
+                        };
+                        seed.0 = replay.seed;
+                        number_of_players.0 = replay.number_of_players;
+                        commands.insert_resource(StartingSnapshot(replay.start.clone()));
+                        commands.insert_resource(replay.input_buffers.clone());
+                        commands.insert_resource(replay.rules.clone());
+                        commands.insert_resource(SharedRules);
+                        commands.insert_resource(Playback {
+                            replay,
+                            next_input: 0,
+                            seek: None,
+                        });
+                        app_state_next_state.set(AppState::Replay);
+                    });
+                }
+            });
+    });
+}
+
+#[derive(Resource)]
+struct Playback {
+    replay: Replay,
+    /// Index of the next input to feed into the simulation
+    next_input: usize,
+    /// Tick to jump to on the next frame
+    seek: Option<u32>,
+}
+
+fn feed_inputs(
+    mut playback: ResMut<Playback>,
+    tick_count: Res<TickCount>,
+    mut propose_direction: MessageWriter<ProposeDirection>,
+) {
+    while let Some(&(tick, id, direction)) = playback.replay.inputs.get(playback.next_input) {
+        if tick > tick_count.0 {
+            break;
+        }
+        playback.next_input += 1;
+        propose_direction.write(ProposeDirection {
+            id: Id(id),
+            direction,
+        });
+    }
+}
+
+/// Plays the whole replay without a window, like watching it to the end
+#[cfg(test)]
+pub(crate) fn simulate(replay: &Replay) -> App {
+    use crate::headless::{headless_app_with, step};
+
+    let mut app = headless_app_with(replay.number_of_players, replay.seed, |app| {
+        app.insert_resource(StartingSnapshot(replay.start.clone()))
+            .insert_resource(replay.input_buffers.clone())
+            .insert_resource(replay.rules.clone())
+            .insert_resource(Playback {
+                replay: replay.clone(),
+                next_input: 0,
+                seek: None,
+            })
+            .add_systems(FixedPreUpdate, feed_inputs);
+    });
+    let world = app.world_mut();
+    while world.resource::<TickCount>().0 < replay.ticks {
+        step(world);
+    }
+    app
+}
+
+/// Jumps to the requested tick
+/// Going backwards starts the match again and fast forwards from the beginning
+fn seek(world: &mut World) {
+    let Some(target) = world.resource_mut::<Playback>().seek.take() else {
+        return;
+    };
+
+    if target < world.resource::<TickCount>().0 {
+        world.run_schedule(OnEnter(InMatch));
+        world.resource_mut::<Playback>().next_input = 0;
+    }
+
+    while world.resource::<TickCount>().0 < target {
+        run_tick(world);
+    }
+    // Jumping shows none of the effects of the ticks it goes through
+    world.resource_mut::<Messages<AppleEaten>>().clear();
+    world.resource_mut::<Messages<Collision>>().clear();
+    world.resource_mut::<Messages<RemoveChunks>>().clear();
+    world.resource_mut::<Messages<Won>>().clear();
+}
+
+fn stop_at_end(
+    playback: Res<Playback>,
+    tick_count: Res<TickCount>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if tick_count.0 >= playback.replay.ticks && !time.is_paused() {
+        time.pause();
+    }
+}
+
+fn playback_controls(
+    mut contexts: EguiContexts,
+    mut playback: ResMut<Playback>,
+    tick_count: Res<TickCount>,
+    mut time: ResMut<Time<Virtual>>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    let ticks = playback.replay.ticks;
+    egui::Window::new("Replay").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if time.is_paused() {
+                if ui.button("Play").clicked() {
+                    if tick_count.0 >= ticks {
+                        playback.seek = Some(0);
+                    }
+                    time.unpause();
+                }
+                if ui.button("Step").clicked() {
+                    playback.seek = Some((tick_count.0 + 1).min(ticks));
+                }
+            } else if ui.button("Pause").clicked() {
+                time.pause();
+            }
+            if ui.button("Exit").clicked() {
+                app_state_next_state.set(AppState::MainMenu);
+            }
+        });
+
+        let mut speed = time.relative_speed();
+        if ui
+            .add(
+                egui::Slider::new(&mut speed, 0.25..=8.0)
+                    .logarithmic(true)
+                    .text("Speed"),
+            )
+            .changed()
+        {
+            time.set_relative_speed(speed);
+        }
+
+        let mut tick = tick_count.0;
+        if ui
+            .add(egui::Slider::new(&mut tick, 0..=ticks).text("Tick"))
+            .changed()
+        {
+            playback.seek = Some(tick);
+        }
+    });
+}
+
+fn reset_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+    time.set_relative_speed(1.0);
+}
+
+fn restore_rules(mut commands: Commands, local_rules: Res<LocalRules>) {
+    commands.remove_resource::<SharedRules>();
+    commands.insert_resource(local_rules.0.clone());
+}
+
+fn forget_starting_snapshot(mut starting_snapshot: ResMut<StartingSnapshot>) {
+    starting_snapshot.0 = None;
+}
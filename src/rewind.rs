@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+
+use crate::{
+    game_state::{AppState, InMatch},
+    schedule::tick_gate_open,
+    snapshot::{capture, restore, GameSnapshot, SnapshotRestored},
+};
+
+/// How many ticks are kept around, 30 seconds at the default tick rate
+const REWIND_BUFFER_TICKS: usize = 300;
+/// How far back the practice hotkey goes, 3 seconds at the default tick rate
+const REWIND_TICKS: usize = 30;
+const REWIND_KEY: KeyCode = KeyCode::Backspace;
+const TIMELINE_KEY: KeyCode = KeyCode::F3;
+
+pub(crate) struct RewindPlugin;
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RewindBuffer>()
+            .init_resource::<PracticeMode>()
+            .init_resource::<Timeline>()
+            .add_systems(OnEnter(InMatch), clear_buffer)
+            .add_systems(
+                FixedPostUpdate,
+                record_tick.run_if(in_state(InMatch).and(tick_gate_open)),
+            )
+            .add_systems(
+                Update,
+                (
+                    rewind_hotkey.run_if(in_state(AppState::InGame)),
+                    toggle_timeline.run_if(in_state(InMatch)),
+                ),
+            )
+            .add_systems(OnExit(InMatch), close_timeline)
+            .add_systems(
+                EguiPrimaryContextPass,
+                (
+                    practice_mode_selection.run_if(in_state(AppState::MainMenu)),
+                    timeline_window.run_if(in_state(InMatch)),
+                ),
+            );
+    }
+}
+
+/// The state of the game after each of the last ticks, oldest first
+#[derive(Resource, Default)]
+struct RewindBuffer(VecDeque<GameSnapshot>);
+
+/// Allows going back in time with [`REWIND_KEY`]
+#[derive(Resource, Default)]
+struct PracticeMode(bool);
+
+/// Debug view to inspect the buffered ticks
+#[derive(Resource, Default)]
+struct Timeline {
+    open: bool,
+    /// Index in the [`RewindBuffer`] being inspected
+    selected: usize,
+    /// Time stays paused on closing if it already was on opening, like a paused replay
+    was_paused: bool,
+}
+
+fn clear_buffer(mut buffer: ResMut<RewindBuffer>) {
+    buffer.0.clear();
+}
+
+fn record_tick(world: &mut World) {
+    let snapshot = capture(world);
+    let mut buffer = world.resource_mut::<RewindBuffer>();
+    if buffer.0.len() == REWIND_BUFFER_TICKS {
+        buffer.0.pop_front();
+    }
+    buffer.0.push_back(snapshot);
+}
+
+/// Goes back to the tick at `index` in the buffer, forgetting everything after it
+fn rewind_to(world: &mut World, index: usize) {
+    let snapshot = {
+        let mut buffer = world.resource_mut::<RewindBuffer>();
+        buffer.0.truncate(index + 1);
+        buffer.0.back().cloned()
+    };
+
+    if let Some(snapshot) = snapshot {
+        restore(world, &snapshot);
+        world.write_message(SnapshotRestored(snapshot));
+    }
+}
+
+fn rewind_hotkey(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    practice_mode: Res<PracticeMode>,
+    buffer: Res<RewindBuffer>,
+) {
+    if !practice_mode.0 || !keyboard_input.just_pressed(REWIND_KEY) {
+        return;
+    }
+    let index = buffer.0.len().saturating_sub(REWIND_TICKS + 1);
+    commands.queue(move |world: &mut World| rewind_to(world, index));
+}
+
+fn toggle_timeline(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut timeline: ResMut<Timeline>,
+    buffer: Res<RewindBuffer>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if !keyboard_input.just_pressed(TIMELINE_KEY) {
+        return;
+    }
+    timeline.open = !timeline.open;
+    if timeline.open {
+        timeline.selected = buffer.0.len().saturating_sub(1);
+        timeline.was_paused = time.is_paused();
+        time.pause();
+    } else if !timeline.was_paused {
+        time.unpause();
+    }
+}
+
+fn close_timeline(mut timeline: ResMut<Timeline>, mut time: ResMut<Time<Virtual>>) {
+    if timeline.open {
+        timeline.open = false;
+        if !timeline.was_paused {
+            time.unpause();
+        }
+    }
+}
+
+fn practice_mode_selection(mut contexts: EguiContexts, mut practice_mode: ResMut<PracticeMode>) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Practice").show(ctx, |ui| {
+        ui.checkbox(&mut practice_mode.0, "Practice mode");
+        ui.label(format!(
+            "`{:?}` rewinds {} ticks while playing",
+            REWIND_KEY, REWIND_TICKS
+        ));
+    });
+}
+
+fn timeline_window(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut timeline: ResMut<Timeline>,
+    buffer: Res<RewindBuffer>,
+    app_state: Res<State<AppState>>,
+) {
+    if !timeline.open {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Timeline").show(ctx, |ui| {
+        let Some(last) = buffer.0.len().checked_sub(1) else {
+            ui.label("No ticks recorded yet");
+            return;
+        };
+        timeline.selected = timeline.selected.min(last);
+        ui.add(egui::Slider::new(&mut timeline.selected, 0..=last).text("Buffered tick"));
+
+        let snapshot = &buffer.0[timeline.selected];
+        ui.label(format!("Tick {}", snapshot.tick));
+
+        // Going back while replaying would desync the replay from its inputs
+        if *app_state.get() == AppState::InGame && ui.button("Rewind to here").clicked() {
+            let index = timeline.selected;
+            commands.queue(move |world: &mut World| rewind_to(world, index));
+        }
+
+        egui::Grid::new("timeline_snakes")
+            .striped(true)
+            .show(ui, |ui| {
+                for header in [
+                    "Snake",
+                    "Direction",
+                    "Next directions",
+                    "Trail",
+                    "Inmortal ticks",
+                    "Segments",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for snake in snapshot.snakes.iter() {
+                    ui.label(&snake.name);
+                    ui.label(format!("{:?}", snake.direction));
+                    ui.label(format!("{:?}", snake.next_directions));
+                    ui.label(format!("{}", snake.trail.0));
+                    ui.label(snake.inmortal_ticks.to_string());
+                    ui.label(
+                        snake
+                            .segments
+                            .iter()
+                            .map(|segment| format!("{}", segment.0))
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    );
+                    ui.end_row();
+                }
+            });
+    });
+}
@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{game_state::InMatch, schedule::MatchSetupSet};
+
+/// Randomness used by the game rules
+/// It's seeded from [`Seed`] at the start of every match so the match can be simulated again
+#[derive(Resource)]
+pub(crate) struct GameRng(pub(crate) ChaCha8Rng);
+
+/// Seed of the current match
+#[derive(Resource)]
+pub(crate) struct Seed(pub(crate) u64);
+
+pub(crate) struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Seed(rand::random()))
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(0)))
+            .add_systems(OnEnter(InMatch), seed_rng.in_set(MatchSetupSet::Spawn))
+            .add_systems(OnExit(InMatch), pick_next_seed);
+    }
+}
+
+pub(crate) fn seed_rng(seed: Res<Seed>, mut rng: ResMut<GameRng>) {
+    rng.0 = ChaCha8Rng::seed_from_u64(seed.0);
+}
+
+fn pick_next_seed(mut seed: ResMut<Seed>) {
+    seed.0 = rand::random();
+}
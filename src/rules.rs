@@ -0,0 +1,326 @@
+//! The rules a match is played with, from `assets/game.rules.ron` and the launch options
+//! The file is watched natively, saving it changes the rules from the next match on, without restarting the game
+//! A match nothing happened in yet, like the first one while the file loads, starts over with them right away
+//! Replays and network matches carry their own, so the match is simulated the same way everywhere
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    ecs::error::BevyError,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    direction::Direction,
+    game_state::{AppState, InMatch},
+    launch::LaunchOptions,
+    movement::TickCount,
+    HALF_LEN, MAX_NUMBER_OF_PLAYERS,
+};
+
+const RULES_PATH: &str = "game.rules.ron";
+
+pub(crate) struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRules>()
+            .init_resource::<LocalRules>();
+    }
+
+    // Once every plugin is added, so a headless app can still add assets when setting up
+    fn finish(&self, app: &mut App) {
+        // Headless apps usually have no assets, they play with the rules they are given
+        if !app.is_plugin_added::<AssetPlugin>() {
+            return;
+        }
+        app.init_asset::<GameRules>()
+            .init_asset_loader::<RulesLoader>()
+            .add_systems(Startup, load_rules_file)
+            .add_systems(Update, reload_rules.run_if(resource_exists::<RulesFile>))
+            .add_systems(
+                OnExit(InMatch),
+                use_local_rules.run_if(not(resource_exists::<SharedRules>)),
+            );
+    }
+}
+
+/// The rules in effect
+#[derive(Asset, TypePath, Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub(crate) struct GameRules {
+    /// Seconds between ticks
+    pub(crate) tick_seconds: f64,
+    pub(crate) board: Board,
+    /// Ticks a snake can't be hit for after hitting something
+    pub(crate) inmortal_ticks: u8,
+    /// Part of the snake lost on each hit, rounded up, the head is never lost
+    pub(crate) proportion_lost_per_hit: f32,
+    /// The longest snake wins once it's at least this long...
+    pub(crate) length_to_win: usize,
+    /// ...and stays the longest for this many seconds
+    pub(crate) hold_time_to_win: f32,
+    /// Seconds between blinks of a snake that can't be hit
+    pub(crate) blink_seconds: f32,
+    /// Side of the square drawn on each cell, a cell is 1 wide
+    pub(crate) cell_size: f32,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            tick_seconds: 0.1,
+            board: Board::new(HALF_LEN),
+            inmortal_ticks: 10,
+            proportion_lost_per_hit: 0.3,
+            length_to_win: 10,
+            hold_time_to_win: 10.0,
+            blink_seconds: 0.1,
+            cell_size: 0.8,
+        }
+    }
+}
+
+impl GameRules {
+    /// Reads the contents of a rules file, failing if the rules aren't valid
+    pub(crate) fn from_ron(contents: &str) -> Result<Self, String> {
+        let rules = ron::from_str::<GameRules>(contents).map_err(|error| error.to_string())?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !self.tick_seconds.is_finite() || self.tick_seconds <= 0.0 {
+            return Err("The seconds between ticks have to be above 0".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.proportion_lost_per_hit) {
+            return Err("The proportion lost per hit has to be from 0 to 1".to_string());
+        }
+        if !self.hold_time_to_win.is_finite() || self.hold_time_to_win < 0.0 {
+            return Err("The time to hold the lead can't be negative".to_string());
+        }
+        if !self.blink_seconds.is_finite() || self.blink_seconds <= 0.0 {
+            return Err("The seconds between blinks have to be above 0".to_string());
+        }
+        self.board.validate()
+    }
+}
+
+/// A square board that wraps around, cells go from `-half_len` to `half_len` in both axes
+/// A map file is one of these as RON
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct Board {
+    pub(crate) half_len: i32,
+    /// Where the snake of each player starts, by player number starting at 1
+    pub(crate) spawns: Vec<Spawn>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Spawn {
+    pub(crate) cell: IVec2,
+    pub(crate) direction: Direction,
+}
+
+impl Board {
+    /// The smallest board every player still fits in
+    pub(crate) const MIN_HALF_LEN: i32 = 2;
+
+    /// A board with the snakes starting around the center, each one heading to the next corner
+    pub(crate) fn new(half_len: i32) -> Self {
+        let offset = (half_len / 2).max(1);
+        let spawn = |x, y, direction| Spawn {
+            cell: IVec2::new(x, y) * offset,
+            direction,
+        };
+        Self {
+            half_len,
+            spawns: vec![
+                spawn(-1, -1, Direction::Right),
+                spawn(1, 1, Direction::Left),
+                spawn(-1, 1, Direction::Down),
+                spawn(1, -1, Direction::Up),
+            ],
+        }
+    }
+
+    /// Cells on each side
+    pub(crate) fn side(&self) -> i32 {
+        2 * self.half_len + 1
+    }
+
+    /// Reads a map file, failing if it isn't a valid board
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Could not read the map {}: {}", path, error))?;
+        let board = ron::from_str::<Board>(&contents)
+            .map_err(|error| format!("Invalid map {}: {}", path, error))?;
+        board.validate()?;
+        Ok(board)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.half_len < Self::MIN_HALF_LEN {
+            return Err(format!(
+                "The board has to be at least {} cells wide",
+                2 * Self::MIN_HALF_LEN + 1
+            ));
+        }
+        if self.spawns.len() < MAX_NUMBER_OF_PLAYERS {
+            return Err(format!(
+                "The board needs a spawn for each of the {} players",
+                MAX_NUMBER_OF_PLAYERS
+            ));
+        }
+        if let Some(spawn) = self
+            .spawns
+            .iter()
+            .find(|spawn| spawn.cell.abs().max_element() > self.half_len)
+        {
+            return Err(format!("The spawn at {} is off the board", spawn.cell));
+        }
+        Ok(())
+    }
+}
+
+/// The rules from the file with the launch options on top
+/// They are in effect unless there are [`SharedRules`]
+#[derive(Resource, Default)]
+pub(crate) struct LocalRules(pub(crate) GameRules);
+
+/// The rules in effect came from a replay or from the host of a network match, so changes to the file wait
+/// Whoever inserts this puts the [`LocalRules`] back when removing it
+#[derive(Resource)]
+pub(crate) struct SharedRules;
+
+#[derive(Resource)]
+struct RulesFile(Handle<GameRules>);
+
+#[derive(Default)]
+struct RulesLoader;
+
+impl AssetLoader for RulesLoader {
+    type Asset = GameRules;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        // A broken file keeps the rules from before
+        Ok(GameRules::from_ron(std::str::from_utf8(&bytes)?)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.ron"]
+    }
+}
+
+fn load_rules_file(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RulesFile(asset_server.load(RULES_PATH)));
+}
+
+/// Changes wait for the match to end, see [`use_local_rules`], so a match and its replay play by the same rules
+#[allow(clippy::too_many_arguments)]
+fn reload_rules(
+    mut commands: Commands,
+    mut asset_events: MessageReader<AssetEvent<GameRules>>,
+    rules_file: Res<RulesFile>,
+    files: Res<Assets<GameRules>>,
+    launch_options: Option<Res<LaunchOptions>>,
+    shared_rules: Option<Res<SharedRules>>,
+    app_state: Res<State<AppState>>,
+    tick_count: Res<TickCount>,
+    mut local_rules: ResMut<LocalRules>,
+    mut rules: ResMut<GameRules>,
+) {
+    let id = rules_file.0.id();
+    if !asset_events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id))
+    {
+        return;
+    }
+    let Some(file) = files.get(id) else {
+        return;
+    };
+
+    local_rules.0 = file.clone();
+    if let Some(launch_options) = launch_options {
+        launch_options.override_rules(&mut local_rules.0);
+    }
+    if shared_rules.is_some() || *rules == local_rules.0 {
+        return;
+    }
+    match app_state.get() {
+        AppState::MainMenu => {}
+        AppState::InGame if tick_count.0 == 0 => {
+            commands.queue(|world: &mut World| world.run_schedule(OnEnter(InMatch)));
+        }
+        AppState::InGame | AppState::Replay => return,
+    }
+    *rules = local_rules.0.clone();
+}
+
+fn use_local_rules(local_rules: Res<LocalRules>, mut rules: ResMut<GameRules>) {
+    rules.set_if_neq(local_rules.0.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{coordinate::Coordinate, headless::headless_app_with, snake::Snake};
+
+    #[test]
+    fn the_first_match_plays_by_the_rules_file() {
+        let rules = GameRules {
+            tick_seconds: 0.25,
+            board: Board::new(9),
+            ..default()
+        };
+        let directory = std::env::temp_dir().join(format!("snake_rules_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join(RULES_PATH), ron::to_string(&rules).unwrap()).unwrap();
+
+        let mut app = headless_app_with(2, 0, |app| {
+            app.add_plugins(AssetPlugin {
+                file_path: directory.to_string_lossy().into_owned(),
+                ..default()
+            })
+            .init_resource::<ButtonInput<KeyCode>>()
+            // Frames also run systems for the window and input, which are skipped without them
+            .set_error_handler(bevy::ecs::error::ignore);
+        });
+        // So no tick runs while the file loads
+        app.world_mut().resource_mut::<Time<Virtual>>().pause();
+        for _ in 0..1000 {
+            if *app.world().resource::<GameRules>() == rules {
+                break;
+            }
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let world = app.world_mut();
+        assert_eq!(*world.resource::<GameRules>(), rules);
+        assert_eq!(
+            world.resource::<Time<Fixed>>().timestep(),
+            Duration::from_secs_f64(rules.tick_seconds)
+        );
+        let mut snakes = world.query::<&Snake>();
+        for snake in snakes.iter(world) {
+            let spawn = rules.board.spawns[usize::from(snake.player_number.0) - 1];
+            let head = world.get::<Coordinate>(snake.segments[0]).unwrap();
+            assert_eq!(head.0, spawn.cell.as_vec2());
+            assert_eq!(snake.direction, spawn.direction);
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use bevy::prelude::*;
+use bevy::{app::FixedMain, prelude::*};
+
+use crate::game_state::InMatch;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum InGameSet {
@@ -6,6 +8,46 @@ pub enum InGameSet {
     Last,
 }
 
+/// The steps of a single snake tick
+/// Every run of `FixedUpdate` is exactly one tick, so the game can be re-simulated tick by tick
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub enum TickSet {
+    /// Queue the directions proposed since the last tick
+    Input,
+    /// Move every snake one cell forward
+    Movement,
+    /// Wrap whatever left the board back into it
+    Wrap,
+    Eat,
+    Grow,
+    Collision,
+    Win,
+}
+
+/// Setting up the board when a match starts
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub enum MatchSetupSet {
+    /// Clear the previous match and spawn the default board
+    Spawn,
+    /// Replace the default board, for example with a snapshot
+    Override,
+}
+
+/// Whether the next tick can run
+/// Something outside the game rules, like the network, can close it to hold the game back
+#[derive(Resource)]
+pub(crate) struct TickGate(pub(crate) bool);
+
+impl Default for TickGate {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+pub(crate) fn tick_gate_open(tick_gate: Res<TickGate>) -> bool {
+    tick_gate.0
+}
+
 pub struct SchedulePlugin;
 
 impl Plugin for SchedulePlugin {
@@ -24,6 +66,37 @@ impl Plugin for SchedulePlugin {
             ApplyDeferred
                 .after(InGameSet::SpawnDespawnEntities)
                 .before(InGameSet::Last),
-        );
+        )
+        .configure_sets(
+            FixedUpdate,
+            (
+                TickSet::Input,
+                TickSet::Movement,
+                TickSet::Wrap,
+                TickSet::Eat,
+                TickSet::Grow,
+                TickSet::Collision,
+                TickSet::Win,
+            )
+                .chain()
+                .run_if(in_state(InMatch).and(tick_gate_open)),
+        )
+        .configure_sets(
+            OnEnter(InMatch),
+            (MatchSetupSet::Spawn, MatchSetupSet::Override).chain(),
+        )
+        .init_resource::<TickGate>();
     }
 }
+
+/// Runs one snake tick right away, without waiting for the fixed timestep
+/// Useful to fast forward the game, for example when scrubbing through a replay
+pub(crate) fn run_tick(world: &mut World) {
+    let mut fixed_time = world.resource_mut::<Time<Fixed>>();
+    let timestep = fixed_time.timestep();
+    // Same as the fixed main loop does, so timers in the game rules advance the same way
+    fixed_time.advance_by(timestep);
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    world.run_schedule(FixedMain);
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
@@ -8,7 +8,7 @@ impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup).add_systems(
             Update,
-            (update_score).run_if(in_state(game_state::AppState::InGame)),
+            (update_score).run_if(in_state(game_state::InMatch)),
         );
     }
 }
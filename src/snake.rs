@@ -1,11 +1,19 @@
 use std::collections::VecDeque;
 
 use bevy::{camera::ScalingMode, color::palettes::css, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    apple::AppleEaten, coordinate::Coordinate, direction::Direction, game_state::AppState,
-    main_menu::NumberOfPlayersSelected, schedule::InGameSet, BOARD_VIEWPORT_IN_WORLD_UNITS,
-    HALF_LEN, SIZE,
+    apple::AppleEaten,
+    asset_loader::SceneAssets,
+    coordinate::Coordinate,
+    direction::Direction,
+    game_state::InMatch,
+    main_menu::NumberOfPlayersSelected,
+    rules::GameRules,
+    schedule::{InGameSet, MatchSetupSet, TickSet},
+    theme::{ActiveTheme, GridStyle},
+    PADDING,
 };
 
 pub(crate) struct SnakePlugin;
@@ -14,67 +22,113 @@ const TILE_SIZE: f32 = 1.0;
 
 impl Plugin for SnakePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_grid_and_camera)
+        app.add_systems(Startup, setup_camera)
+            .add_systems(
+                Update,
+                draw_board.run_if(
+                    resource_changed::<GameRules>.or(resource_exists_and_changed::<ActiveTheme>),
+                ),
+            )
+            .add_systems(FixedUpdate, toroid_coordinates.in_set(TickSet::Wrap))
+            .add_systems(FixedUpdate, grow_snake.in_set(TickSet::Grow))
             .add_systems(
                 Update,
                 (
-                    grow_snake,
-                    ApplyDeferred,
-                    toroid_coordinates,
                     add_sprite_bundles,
                     ApplyDeferred, // This is needed in order to render the sprites correctly, we need to flush the sprites into the world and then update their transforms
                     set_sprite_size,
+                    set_snake_pieces,
                     update_local_coordinates_to_world_transforms,
                 )
                     .chain()
                     .in_set(InGameSet::Last)
-                    .run_if(in_state(AppState::InGame)),
+                    .run_if(in_state(InMatch)),
             )
             .add_systems(
-                OnEnter(AppState::InGame),
-                (despawn_snakes, spawn_snakes).chain(),
+                OnEnter(InMatch),
+                (despawn_snakes, spawn_snakes)
+                    .chain()
+                    .in_set(MatchSetupSet::Spawn),
             );
     }
 }
 
-fn setup_grid_and_camera(mut commands: Commands) {
-    let mut grid = vec![];
+fn setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2d,
+        Projection::Orthographic(OrthographicProjection {
+            far: 1000.,
+            near: -1000.,
+            ..OrthographicProjection::default_2d()
+        }),
+    ));
+}
 
-    for x in -HALF_LEN..=HALF_LEN {
-        for y in -HALF_LEN..=HALF_LEN {
+/// A cell of the board
+#[derive(Component)]
+struct GridCell;
+
+/// Lays the cells out again and fits the camera to them whenever the board might have changed
+fn draw_board(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    theme: Option<Res<ActiveTheme>>,
+    cells: Query<Entity, With<GridCell>>,
+    mut projections: Query<&mut Projection, With<Camera2d>>,
+) {
+    for cell in cells.iter() {
+        commands.entity(cell).despawn();
+    }
+
+    // Until the theme is loaded
+    let (color, grid_style) = theme.map_or(
+        (Color::Srgba(css::DARK_SLATE_GRAY), GridStyle::Solid),
+        |theme| (theme.0.board, theme.0.grid),
+    );
+    let half_len = rules.board.half_len;
+    let mut grid = vec![];
+    for x in -half_len..=half_len {
+        for y in -half_len..=half_len {
+            let color = match grid_style {
+                GridStyle::Checkered(other) if (x + y) % 2 != 0 => other,
+                _ => color,
+            };
             grid.push((
                 Sprite {
-                    custom_size: Some(Vec2 { x: SIZE, y: SIZE }),
-                    color: Color::Srgba(css::DARK_SLATE_GRAY),
+                    custom_size: Some(Vec2::splat(rules.cell_size)),
+                    color,
                     ..Default::default()
                 },
                 Coordinate(Vec2::new(x as f32, y as f32)),
                 Depth(-1.0),
+                // Placed right away, the board also shows outside of matches
+                Transform::from_xyz(x as f32, y as f32, -1.0),
+                GridCell,
             ));
         }
     }
     commands.spawn_batch(grid);
 
-    commands.spawn((
-        Camera2d,
-        Projection::Orthographic(OrthographicProjection {
-            far: 1000.,
-            near: -1000.,
-            scaling_mode: ScalingMode::AutoMin {
-                min_width: BOARD_VIEWPORT_IN_WORLD_UNITS,
-                min_height: BOARD_VIEWPORT_IN_WORLD_UNITS,
-            },
-            ..OrthographicProjection::default_2d()
-        }),
-    ));
+    let viewport = (2 * half_len) as f32 + 2.0 * PADDING;
+    for mut projection in projections.iter_mut() {
+        if let Projection::Orthographic(orthographic) = projection.as_mut() {
+            orthographic.scaling_mode = ScalingMode::AutoMin {
+                min_width: viewport,
+                min_height: viewport,
+            };
+        }
+    }
 }
 
-fn spawn_snakes(mut commands: Commands, number_of_players: Res<NumberOfPlayersSelected>) {
+fn spawn_snakes(
+    mut commands: Commands,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    rules: Res<GameRules>,
+    theme: Option<Res<ActiveTheme>>,
+) {
     let mut spawn_snake =
         |id, spawn_coord: Coordinate, direction: Direction, color: MyColor, name: String| {
-            let head_a = commands
-                .spawn((color, SnakeSegment, spawn_coord.clone(), Tile))
-                .id();
+            let head_a = commands.spawn(segment(color, spawn_coord.clone())).id();
 
             commands.spawn((
                 Snake {
@@ -94,29 +148,21 @@ fn spawn_snakes(mut commands: Commands, number_of_players: Res<NumberOfPlayersSe
     let snakes = [
         (
             Id(1),
-            Coordinate::from((-3.0, -3.0)),
-            Direction::Right,
             MyColor(Color::Srgba(css::LIMEGREEN)),
             "Ninja".to_string(),
         ),
         (
             Id(2),
-            Coordinate::from((3.0, 3.0)),
-            Direction::Left,
             MyColor(Color::Srgba(css::PINK)),
             "Panther".to_string(),
         ),
         (
             Id(3),
-            Coordinate::from((-3.0, 3.0)),
-            Direction::Down,
             MyColor(Color::Srgba(css::SALMON)),
             "Sushi".to_string(),
         ),
         (
             Id(4),
-            Coordinate::from((3.0, -3.0)),
-            Direction::Up,
             MyColor(Color::Srgba(css::TURQUOISE)),
             "Sonic".to_string(),
         ),
@@ -124,8 +170,16 @@ fn spawn_snakes(mut commands: Commands, number_of_players: Res<NumberOfPlayersSe
 
     snakes
         .into_iter()
+        .zip(rules.board.spawns.iter())
         .take(number_of_players.0)
-        .map(|(id, coord, direction, color, name)| spawn_snake(id, coord, direction, color, name))
+        .map(|((id, color, name), spawn)| {
+            let coord = Coordinate(spawn.cell.as_vec2());
+            let color = theme
+                .as_ref()
+                .and_then(|theme| theme.0.skin(id.0))
+                .map_or(color, |skin| MyColor(skin.color));
+            spawn_snake(id, coord, spawn.direction, color, name)
+        })
         .count();
 }
 
@@ -150,12 +204,16 @@ pub(crate) struct Snake {
     pub(crate) inmortal_ticks: u8,
 }
 
-#[derive(Component, Debug, PartialEq, Clone)]
+#[derive(Component, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub(crate) struct Id(pub(crate) u8);
 
 #[derive(Component)]
 pub(crate) struct SnakeSegment;
 
+pub(crate) fn segment(color: MyColor, coordinate: Coordinate) -> impl Bundle {
+    (color, SnakeSegment, coordinate, Tile)
+}
+
 fn grow_snake(
     mut commands: Commands,
     mut query: Query<(&mut Snake, &MyColor)>,
@@ -163,9 +221,7 @@ fn grow_snake(
 ) {
     for AppleEaten(entity) in apple_eaten.read() {
         if let Ok((mut snake, &color)) = query.get_mut(*entity) {
-            let tail = commands
-                .spawn((color, SnakeSegment, snake.trail.clone(), Tile))
-                .id();
+            let tail = commands.spawn(segment(color, snake.trail.clone())).id();
 
             snake.segments.push_back(tail);
         }
@@ -174,13 +230,15 @@ fn grow_snake(
 
 fn toroid_coordinates(
     mut query: Query<&mut Coordinate, (With<SnakeSegment>, Changed<Coordinate>)>,
+    rules: Res<GameRules>,
 ) {
+    let half_len = rules.board.half_len as f32;
     for mut coordinate in query.iter_mut() {
-        if coordinate.0.x.abs() > HALF_LEN as f32 {
-            coordinate.0.x = -coordinate.0.x.signum() * HALF_LEN as f32;
+        if coordinate.0.x.abs() > half_len {
+            coordinate.0.x = -coordinate.0.x.signum() * half_len;
         }
-        if coordinate.0.y.abs() > HALF_LEN as f32 {
-            coordinate.0.y = -coordinate.0.y.signum() * HALF_LEN as f32;
+        if coordinate.0.y.abs() > half_len {
+            coordinate.0.y = -coordinate.0.y.signum() * half_len;
         }
     }
 }
@@ -193,7 +251,7 @@ pub(crate) struct MyColor(pub(crate) Color);
 #[derive(Component)]
 pub(crate) struct Depth(pub(crate) f32);
 
-fn update_local_coordinates_to_world_transforms(
+pub(crate) fn update_local_coordinates_to_world_transforms(
     mut query: Query<
         (&Coordinate, &mut Transform, Option<&Depth>),
         Or<(Changed<Coordinate>, Changed<Transform>)>,
@@ -208,15 +266,132 @@ fn update_local_coordinates_to_world_transforms(
 fn add_sprite_bundles(
     query: Query<(Entity, &MyColor), (Changed<Coordinate>, Without<Transform>)>,
     mut commands: Commands,
+    assets: Res<SceneAssets>,
 ) {
     for (entity, color) in query.iter() {
         commands.entity(entity).insert(Sprite {
             color: color.0,
-            ..Default::default()
+            // The skin of its snake comes in `set_snake_pieces`
+            ..Sprite::from_atlas_image(
+                default(),
+                TextureAtlas {
+                    layout: assets.snake_layout.clone(),
+                    index: SnakePiece::Straight as usize,
+                },
+            )
         });
     }
 }
 
+/// The tiles of a snake skin like `snake.png` in order, all of them white so they can be tinted
+/// Each one is drawn as if the snake went from left to right
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum SnakePiece {
+    /// Looking right
+    Head,
+    /// Left to right
+    Straight,
+    /// Right to up
+    Corner,
+    /// The rest of the snake to the right
+    Tail,
+}
+
+/// Picks the piece and rotation of every segment from the segments next to it, in the skin of its snake
+fn set_snake_pieces(
+    snakes: Query<(&Snake, &MyColor)>,
+    mut segments: Query<(&Coordinate, &mut Sprite, &mut Transform), With<SnakeSegment>>,
+    rules: Res<GameRules>,
+    theme: Option<Res<ActiveTheme>>,
+) {
+    let half_len = rules.board.half_len;
+    for (snake, color) in snakes.iter() {
+        let pieces = theme
+            .as_ref()
+            .and_then(|theme| theme.0.skin(snake.player_number.0))
+            .map(|skin| skin.pieces.clone())
+            .unwrap_or_default();
+        let cells = snake
+            .segments
+            .iter()
+            .map(|&segment| {
+                segments
+                    .get(segment)
+                    .ok()
+                    .map(|(coordinate, ..)| coordinate.0)
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(cells) = cells else {
+            continue;
+        };
+
+        for (index, &segment) in snake.segments.iter().enumerate() {
+            let (piece, facing) = piece(&cells, index, snake.direction, half_len);
+            let Ok((_, mut sprite, mut transform)) = segments.get_mut(segment) else {
+                continue;
+            };
+            if let Some(atlas) = sprite.texture_atlas.as_mut() {
+                atlas.index = piece as usize;
+            }
+            if sprite.image != pieces {
+                sprite.image = pieces.clone();
+            }
+            // Blinking changes the alpha
+            let alpha = sprite.color.alpha();
+            sprite.color = color.0.with_alpha(alpha);
+            let facing: Vec2 = facing.into();
+            transform.rotation = Quat::from_rotation_z(facing.to_angle());
+        }
+    }
+}
+
+/// The piece of the segment at `index` of a snake on `cells`, and where it faces
+fn piece(
+    cells: &[Vec2],
+    index: usize,
+    direction: Direction,
+    half_len: i32,
+) -> (SnakePiece, Direction) {
+    // Towards the head and towards the tail
+    let ahead = index
+        .checked_sub(1)
+        .and_then(|ahead| step(cells[index], cells[ahead], half_len));
+    let behind = cells
+        .get(index + 1)
+        .and_then(|&behind| step(cells[index], behind, half_len));
+    match (ahead, behind) {
+        (None, Some(behind)) if index == 0 => (SnakePiece::Head, !behind),
+        (None, _) if index == 0 => (SnakePiece::Head, direction),
+        (Some(ahead), None) => (SnakePiece::Tail, ahead),
+        (Some(ahead), Some(behind)) if ahead == !behind => (SnakePiece::Straight, ahead),
+        // The corner joins right and up, so right turns to the one with the other a quarter turn counterclockwise
+        (Some(ahead), Some(behind)) if counterclockwise(ahead) == behind => {
+            (SnakePiece::Corner, ahead)
+        }
+        (Some(_), Some(behind)) => (SnakePiece::Corner, behind),
+        // Segments on the same cell, like right after growing
+        _ => (SnakePiece::Straight, direction),
+    }
+}
+
+/// Direction of the cell next to `from` that `to` is in, going around the edges of the board
+fn step(from: Vec2, to: Vec2, half_len: i32) -> Option<Direction> {
+    let side = (2 * half_len + 1) as f32;
+    let offset = (to - from + half_len as f32).rem_euclid(Vec2::splat(side)) - half_len as f32;
+    Direction::ALL
+        .into_iter()
+        .find(|&direction| <Direction as Into<Vec2>>::into(direction) == offset)
+}
+
+fn counterclockwise(direction: Direction) -> Direction {
+    match direction {
+        Direction::Right => Direction::Up,
+        Direction::Up => Direction::Left,
+        Direction::Left => Direction::Down,
+        Direction::Down => Direction::Right,
+    }
+}
+
 #[derive(Component)]
 pub(crate) struct Tile;
 
@@ -228,3 +403,63 @@ fn set_sprite_size(mut query: Query<&mut Sprite, Added<Tile>>) {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF_LEN: i32 = 7;
+
+    fn pieces(cells: &[(f32, f32)], direction: Direction) -> Vec<(SnakePiece, Direction)> {
+        let cells = cells
+            .iter()
+            .map(|&(x, y)| Vec2::new(x, y))
+            .collect::<Vec<_>>();
+        (0..cells.len())
+            .map(|index| piece(&cells, index, direction, HALF_LEN))
+            .collect()
+    }
+
+    #[test]
+    fn a_straight_piece_across_the_edge() {
+        assert_eq!(
+            pieces(&[(-7.0, 0.0), (7.0, 0.0), (6.0, 0.0)], Direction::Right),
+            [
+                (SnakePiece::Head, Direction::Right),
+                (SnakePiece::Straight, Direction::Right),
+                (SnakePiece::Tail, Direction::Right),
+            ]
+        );
+    }
+
+    #[test]
+    fn corners_face_the_way_they_turn() {
+        // Right then up
+        assert_eq!(
+            pieces(&[(0.0, 1.0), (0.0, 0.0), (-1.0, 0.0)], Direction::Up)[1],
+            (SnakePiece::Corner, Direction::Up)
+        );
+        // Right then down, the same corner turned a quarter clockwise
+        assert_eq!(
+            pieces(&[(0.0, -1.0), (0.0, 0.0), (-1.0, 0.0)], Direction::Down)[1],
+            (SnakePiece::Corner, Direction::Left)
+        );
+        // Right then up across the top edge
+        assert_eq!(
+            pieces(&[(0.0, -7.0), (0.0, 7.0), (-1.0, 7.0)], Direction::Up)[1],
+            (SnakePiece::Corner, Direction::Up)
+        );
+    }
+
+    #[test]
+    fn a_head_across_the_edge_faces_away_from_its_neck() {
+        assert_eq!(
+            pieces(&[(0.0, -7.0), (0.0, 7.0)], Direction::Up)[0],
+            (SnakePiece::Head, Direction::Up)
+        );
+        assert_eq!(
+            pieces(&[(7.0, 3.0), (-7.0, 3.0)], Direction::Left)[0],
+            (SnakePiece::Head, Direction::Left)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apple::{apple, Apple},
+    blink::Blinking,
+    coordinate::Coordinate,
+    direction::Direction,
+    game_state::{AppState, InMatch},
+    movement::TickCount,
+    rng::GameRng,
+    schedule::MatchSetupSet,
+    snake::{segment, Id, MyColor, Snake, Tile},
+    storage::Storage,
+    win::{CurrentFirst, WinnerHoldTimer},
+};
+
+const QUICK_SAVE_KEY: KeyCode = KeyCode::F5;
+const QUICK_LOAD_KEY: KeyCode = KeyCode::F9;
+const QUICK_SAVE_STORAGE_KEY: &str = "snapshots/quicksave";
+
+pub(crate) struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StartingSnapshot>()
+            .add_message::<SnapshotRestored>()
+            .add_systems(
+                OnEnter(InMatch),
+                restore_starting_snapshot.in_set(MatchSetupSet::Override),
+            )
+            .add_systems(
+                Update,
+                quick_save_and_load.run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Everything that the game rules depend on at a given tick
+/// Written as RON it can also be used to set up a specific scenario, see [`StartingSnapshot`]
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct GameSnapshot {
+    pub(crate) tick: u32,
+    pub(crate) rng: ChaCha8Rng,
+    pub(crate) snakes: Vec<SnakeSnapshot>,
+    pub(crate) apples: Vec<Coordinate>,
+    /// How long the current first has been holding the position
+    /// Kept exact, rounding it would make a re-simulated match end on a different tick
+    pub(crate) winner_hold: Duration,
+    pub(crate) current_first: Option<(String, Color)>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct SnakeSnapshot {
+    pub(crate) name: String,
+    pub(crate) player_number: Id,
+    pub(crate) color: Color,
+    pub(crate) direction: Direction,
+    pub(crate) next_directions: VecDeque<Direction>,
+    pub(crate) trail: Coordinate,
+    pub(crate) inmortal_ticks: u8,
+    /// Head first
+    pub(crate) segments: Vec<Coordinate>,
+}
+
+impl GameSnapshot {
+    /// Reads a scenario file, a snapshot written as RON
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Could not read the scenario {}: {}", path, error))?;
+        ron::from_str::<GameSnapshot>(&contents)
+            .map_err(|error| format!("Invalid scenario {}: {}", path, error))
+    }
+}
+
+/// Board to start every match from instead of the default one
+#[derive(Resource, Default)]
+pub(crate) struct StartingSnapshot(pub(crate) Option<GameSnapshot>);
+
+/// The game jumped to the given snapshot in the middle of a match
+#[derive(Message)]
+pub(crate) struct SnapshotRestored(pub(crate) GameSnapshot);
+
+pub(crate) fn capture(world: &mut World) -> GameSnapshot {
+    let mut snakes = world.query::<(&Snake, &MyColor)>();
+    let mut snakes = snakes
+        .iter(world)
+        .map(|(snake, color)| SnakeSnapshot {
+            name: snake.name.clone(),
+            player_number: snake.player_number.clone(),
+            color: color.0,
+            direction: snake.direction,
+            next_directions: snake.next_directions.clone(),
+            trail: snake.trail.clone(),
+            inmortal_ticks: snake.inmortal_ticks,
+            segments: snake
+                .segments
+                .iter()
+                .filter_map(|&segment| world.get::<Coordinate>(segment).cloned())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    snakes.sort_by_key(|snake| snake.player_number.0);
+
+    let mut apples = world.query_filtered::<&Coordinate, With<Apple>>();
+    let mut apples = apples.iter(world).cloned().collect::<Vec<_>>();
+    apples.sort_by(|a, b| a.0.x.total_cmp(&b.0.x).then(a.0.y.total_cmp(&b.0.y)));
+
+    GameSnapshot {
+        tick: world.resource::<TickCount>().0,
+        rng: world.resource::<GameRng>().0.clone(),
+        snakes,
+        apples,
+        winner_hold: world.resource::<WinnerHoldTimer>().0.elapsed(),
+        current_first: world.resource::<CurrentFirst>().0.clone(),
+    }
+}
+
+/// Replaces the snakes, apples and rule state with the ones in the snapshot
+pub(crate) fn restore(world: &mut World, snapshot: &GameSnapshot) {
+    let mut snakes = world.query::<(Entity, &Snake)>();
+    let mut to_despawn = snakes
+        .iter(world)
+        .flat_map(|(entity, snake)| snake.segments.iter().copied().chain([entity]))
+        .collect::<Vec<_>>();
+    let mut apples = world.query_filtered::<Entity, With<Apple>>();
+    to_despawn.extend(apples.iter(world));
+    for entity in to_despawn {
+        world.despawn(entity);
+    }
+
+    for snake in snapshot.snakes.iter() {
+        let color = MyColor(snake.color);
+        let segments = snake
+            .segments
+            .iter()
+            .map(|coordinate| {
+                let mut entity = world.spawn(segment(color, coordinate.clone()));
+                if snake.inmortal_ticks > 0 {
+                    entity.insert(Blinking);
+                }
+                entity.id()
+            })
+            .collect();
+
+        world.spawn((
+            Snake {
+                name: snake.name.clone(),
+                segments,
+                direction: snake.direction,
+                player_number: snake.player_number.clone(),
+                trail: snake.trail.clone(),
+                next_directions: snake.next_directions.clone(),
+                inmortal_ticks: snake.inmortal_ticks,
+            },
+            color,
+            Tile,
+        ));
+    }
+
+    for coordinate in snapshot.apples.iter() {
+        world.spawn(apple(coordinate.clone()));
+    }
+
+    world.resource_mut::<TickCount>().0 = snapshot.tick;
+    world.resource_mut::<GameRng>().0 = snapshot.rng.clone();
+    world
+        .resource_mut::<WinnerHoldTimer>()
+        .0
+        .set_elapsed(snapshot.winner_hold);
+    world.resource_mut::<CurrentFirst>().0 = snapshot.current_first.clone();
+}
+
+/// Equal for equal states, so instances can compare them without sending the whole snapshot
+pub(crate) fn state_hash(snapshot: &GameSnapshot) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ron::to_string(snapshot)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn restore_starting_snapshot(world: &mut World) {
+    if let Some(snapshot) = world.resource::<StartingSnapshot>().0.clone() {
+        restore(world, &snapshot);
+    }
+}
+
+fn quick_save_and_load(world: &mut World) {
+    let keyboard_input = world.resource::<ButtonInput<KeyCode>>();
+    let save = keyboard_input.just_pressed(QUICK_SAVE_KEY);
+    let load = keyboard_input.just_pressed(QUICK_LOAD_KEY);
+
+    if save {
+        let snapshot = capture(world);
+        world
+            .resource::<Storage>()
+            .save(QUICK_SAVE_STORAGE_KEY, &snapshot);
+    }
+
+    if load {
+        let Some(snapshot) = world
+            .resource::<Storage>()
+            .load::<GameSnapshot>(QUICK_SAVE_STORAGE_KEY)
+        else {
+            return;
+        };
+        restore(world, &snapshot);
+        world.write_message(SnapshotRestored(snapshot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{headless_app, step};
+
+    #[test]
+    fn a_restored_snapshot_plays_on_the_same_way() {
+        let mut original = headless_app(4, 42);
+        let world = original.world_mut();
+        for _ in 0..40 {
+            step(world);
+        }
+        let snapshot = capture(world);
+        let serialized = ron::to_string(&snapshot).unwrap();
+
+        // A different seed, so nothing matches unless it comes from the snapshot
+        let mut restored = headless_app(4, 7);
+        restore(restored.world_mut(), &ron::from_str(&serialized).unwrap());
+        assert_eq!(
+            state_hash(&capture(restored.world_mut())),
+            state_hash(&snapshot)
+        );
+
+        for _ in 0..40 {
+            step(original.world_mut());
+            step(restored.world_mut());
+        }
+        assert_eq!(
+            state_hash(&capture(restored.world_mut())),
+            state_hash(&capture(original.world_mut()))
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Somewhere to keep data between runs of the game
+/// Keys are `/` separated paths without extension, like `records` or `replays/last`
+pub(crate) trait StorageBackend: Send + Sync {
+    fn read(&self, key: &str) -> Option<String>;
+    fn write(&self, key: &str, contents: &str) -> std::io::Result<()>;
+    /// Keys stored directly inside `directory`, sorted
+    fn list(&self, directory: &str) -> Vec<String>;
+}
+
+pub(crate) struct StoragePlugin;
+
+impl Plugin for StoragePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Storage::platform_default());
+    }
+}
+
+/// Persists values as RON through the platform backend
+#[derive(Resource)]
+pub(crate) struct Storage(Box<dyn StorageBackend>);
+
+impl Storage {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn platform_default() -> Self {
+        let directory = dirs::config_dir()
+            .unwrap_or_else(|| std::path::PathBuf::from("."))
+            .join("snake_bevy");
+        Self(Box::new(FileStorage { directory }))
+    }
+
+    // TODO: use the browser's local storage so data survives a page reload
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn platform_default() -> Self {
+        Self(Box::new(MemoryStorage::default()))
+    }
+
+    /// Starts empty every time, for tests
+    #[cfg(test)]
+    pub(crate) fn in_memory() -> Self {
+        Self(Box::new(MemoryStorage::default()))
+    }
+
+    pub(crate) fn load<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let contents = self.0.read(key)?;
+        match ron::from_str(&contents) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!(
+                    "Ignoring stored `{}`, it could not be parsed: {}",
+                    key, error
+                );
+                None
+            }
+        }
+    }
+
+    pub(crate) fn save<T: Serialize>(&self, key: &str, value: &T) {
+        let config = ron::ser::PrettyConfig::default().compact_arrays(true);
+        let result = ron::ser::to_string_pretty(value, config)
+            .map_err(std::io::Error::other)
+            .and_then(|contents| self.0.write(key, &contents));
+
+        if let Err(error) = result {
+            warn!("Could not store `{}`: {}", key, error);
+        }
+    }
+
+    pub(crate) fn list(&self, directory: &str) -> Vec<String> {
+        self.0.list(directory)
+    }
+}
+
+/// Stores each key as a `.ron` file inside the platform config directory
+#[cfg(not(target_arch = "wasm32"))]
+struct FileStorage {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(format!("{}.ron", key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StorageBackend for FileStorage {
+    fn read(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(key)).ok()
+    }
+
+    fn write(&self, key: &str, contents: &str) -> std::io::Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)
+    }
+
+    fn list(&self, directory: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.directory.join(directory)) else {
+            return vec![];
+        };
+        let mut keys = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let name = path.file_stem()?.to_str()?;
+                (path.extension()? == std::ffi::OsStr::new("ron"))
+                    .then(|| format!("{}/{}", directory, name))
+            })
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+}
+
+/// Keeps everything in memory, so nothing survives a restart
+#[cfg(any(target_arch = "wasm32", test))]
+#[derive(Default)]
+struct MemoryStorage(std::sync::Mutex<std::collections::HashMap<String, String>>);
+
+#[cfg(any(target_arch = "wasm32", test))]
+impl StorageBackend for MemoryStorage {
+    fn read(&self, key: &str) -> Option<String> {
+        self.0.lock().ok()?.get(key).cloned()
+    }
+
+    fn write(&self, key: &str, contents: &str) -> std::io::Result<()> {
+        self.0
+            .lock()
+            .map_err(|_| std::io::Error::other("memory storage poisoned"))?
+            .insert(key.to_string(), contents.to_string());
+        Ok(())
+    }
+
+    fn list(&self, directory: &str) -> Vec<String> {
+        let Ok(entries) = self.0.lock() else {
+            return vec![];
+        };
+        let prefix = format!("{}/", directory);
+        let mut keys = entries
+            .keys()
+            .filter(|key| {
+                key.strip_prefix(&prefix)
+                    .is_some_and(|name| !name.contains('/'))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+}
@@ -0,0 +1,327 @@
+//! Looks of the board, snakes and food, picked in the menu
+//! A theme is a directory in `assets/themes` with a `theme.ron` manifest, listed in `assets/themes/themes.ron`
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    ecs::error::BevyError,
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use crate::{
+    apple::Apple,
+    game_state::AppState,
+    snake::{MyColor, Snake},
+    storage::Storage,
+};
+
+const THEMES_PATH: &str = "themes/themes.ron";
+const THEME_KEY: &str = "theme";
+
+pub(crate) struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Theme>()
+            .init_asset::<ThemeList>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_asset_loader::<ThemeListLoader>()
+            .add_systems(Startup, load_themes)
+            .add_systems(
+                Update,
+                (
+                    activate_theme,
+                    apply_theme.run_if(resource_exists_and_changed::<ActiveTheme>),
+                    set_new_fonts.run_if(resource_exists::<ActiveTheme>),
+                )
+                    .chain()
+                    .run_if(resource_exists::<Themes>),
+            )
+            .add_systems(
+                EguiPrimaryContextPass,
+                themes_window.run_if(in_state(AppState::MainMenu).and(resource_exists::<Themes>)),
+            );
+    }
+}
+
+#[derive(Asset, TypePath, Clone)]
+pub(crate) struct Theme {
+    pub(crate) name: String,
+    pub(crate) background: Color,
+    /// Color of the cells
+    pub(crate) board: Color,
+    pub(crate) grid: GridStyle,
+    /// By player number starting at 1, starting over when there are more players
+    pub(crate) snakes: Vec<SnakeSkin>,
+    /// Each apple looks like one of these
+    pub(crate) food: Vec<Handle<Image>>,
+    /// Bevy's font when missing
+    pub(crate) font: Option<Handle<Font>>,
+}
+
+impl Theme {
+    pub(crate) fn skin(&self, player_number: u8) -> Option<&SnakeSkin> {
+        let index = usize::from(player_number).checked_sub(1)?;
+        self.snakes.get(index % self.snakes.len().max(1))
+    }
+
+    pub(crate) fn random_food(&self) -> Handle<Image> {
+        self.food
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum GridStyle {
+    Solid,
+    /// Every other cell has this color instead
+    Checkered(Color),
+}
+
+#[derive(Clone)]
+pub(crate) struct SnakeSkin {
+    /// Tints the pieces
+    pub(crate) color: Color,
+    /// Laid out like `snake.png`
+    pub(crate) pieces: Handle<Image>,
+}
+
+/// The theme being shown, once it's loaded
+#[derive(Resource)]
+pub(crate) struct ActiveTheme(pub(crate) Theme);
+
+#[derive(Asset, TypePath)]
+struct ThemeList(Vec<Handle<Theme>>);
+
+#[derive(Resource)]
+struct Themes {
+    list: Handle<ThemeList>,
+    /// By name, the first theme of the list when missing
+    selected: Option<String>,
+}
+
+// Colors are written as hex, like "#ff8c1a"
+#[derive(Deserialize)]
+struct ThemeManifest {
+    name: String,
+    background: String,
+    board: String,
+    #[serde(default)]
+    grid: GridManifest,
+    snakes: Vec<SkinManifest>,
+    food: Vec<String>,
+    #[serde(default)]
+    font: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+enum GridManifest {
+    #[default]
+    Solid,
+    Checkered(String),
+}
+
+#[derive(Deserialize)]
+struct SkinManifest {
+    color: String,
+    pieces: String,
+}
+
+fn color(hex: &str) -> Result<Color, BevyError> {
+    Ok(Srgba::hex(hex)
+        .map_err(|error| format!("Invalid color {}: {}", hex, error))?
+        .into())
+}
+
+#[derive(Default)]
+struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let manifest = ron::de::from_bytes::<ThemeManifest>(&bytes)?;
+
+        // Next to the manifest, or from the assets directory when starting with /
+        let manifest_path = load_context.asset_path().clone();
+        let file = |name: &str| manifest_path.resolve_embed(name);
+        let snakes = manifest
+            .snakes
+            .iter()
+            .map(|skin| {
+                Ok(SnakeSkin {
+                    color: color(&skin.color)?,
+                    pieces: load_context.load(file(&skin.pieces)?),
+                })
+            })
+            .collect::<Result<Vec<_>, BevyError>>()?;
+        let food = manifest
+            .food
+            .iter()
+            .map(|food| Ok(load_context.load(file(food)?)))
+            .collect::<Result<Vec<_>, BevyError>>()?;
+        let font = match &manifest.font {
+            Some(font) => Some(load_context.load(file(font)?)),
+            None => None,
+        };
+
+        Ok(Theme {
+            name: manifest.name,
+            background: color(&manifest.background)?,
+            board: color(&manifest.board)?,
+            grid: match &manifest.grid {
+                GridManifest::Solid => GridStyle::Solid,
+                GridManifest::Checkered(other) => GridStyle::Checkered(color(other)?),
+            },
+            snakes,
+            food,
+            font,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+#[derive(Default)]
+struct ThemeListLoader;
+
+impl AssetLoader for ThemeListLoader {
+    type Asset = ThemeList;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let directories = ron::de::from_bytes::<Vec<String>>(&bytes)?;
+        let list_path = load_context.asset_path().clone();
+        let themes = directories
+            .iter()
+            .map(|directory| {
+                let path = list_path.resolve_embed(&format!("{}/theme.ron", directory))?;
+                Ok(load_context.load(path))
+            })
+            .collect::<Result<Vec<_>, BevyError>>()?;
+        Ok(ThemeList(themes))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["themes.ron"]
+    }
+}
+
+fn load_themes(mut commands: Commands, asset_server: Res<AssetServer>, storage: Res<Storage>) {
+    commands.insert_resource(Themes {
+        list: asset_server.load(THEMES_PATH),
+        selected: storage.load(THEME_KEY),
+    });
+}
+
+/// Shows the selected theme once it's loaded with everything it uses
+fn activate_theme(
+    mut commands: Commands,
+    themes: Res<Themes>,
+    lists: Res<Assets<ThemeList>>,
+    theme_assets: Res<Assets<Theme>>,
+    asset_server: Res<AssetServer>,
+    active_theme: Option<Res<ActiveTheme>>,
+) {
+    let Some(list) = lists.get(&themes.list) else {
+        return;
+    };
+    let loaded = list
+        .0
+        .iter()
+        .filter_map(|handle| Some((handle, theme_assets.get(handle)?)))
+        .collect::<Vec<_>>();
+    let selected = themes
+        .selected
+        .as_ref()
+        .and_then(|name| loaded.iter().find(|(_, theme)| &theme.name == name))
+        .or_else(|| loaded.first());
+    let Some(&(handle, theme)) = selected else {
+        return;
+    };
+    let already_active = active_theme.is_some_and(|active| active.0.name == theme.name);
+    if !already_active && asset_server.is_loaded_with_dependencies(handle) {
+        commands.insert_resource(ActiveTheme(theme.clone()));
+    }
+}
+
+/// The board and the pieces of the snakes follow the theme on their own
+fn apply_theme(
+    mut commands: Commands,
+    active_theme: Res<ActiveTheme>,
+    mut snakes: Query<(&Snake, &mut MyColor)>,
+    mut apples: Query<&mut Sprite, With<Apple>>,
+    mut fonts: Query<&mut TextFont>,
+) {
+    let theme = &active_theme.0;
+    commands.insert_resource(ClearColor(theme.background));
+    for (snake, mut color) in snakes.iter_mut() {
+        if let Some(skin) = theme.skin(snake.player_number.0) {
+            color.0 = skin.color;
+        }
+    }
+    for mut sprite in apples.iter_mut() {
+        sprite.image = theme.random_food();
+    }
+    for mut font in fonts.iter_mut() {
+        font.font = theme.font.clone().unwrap_or_default();
+    }
+}
+
+fn set_new_fonts(active_theme: Res<ActiveTheme>, mut fonts: Query<&mut TextFont, Added<TextFont>>) {
+    let Some(font) = &active_theme.0.font else {
+        return;
+    };
+    for mut text_font in fonts.iter_mut() {
+        text_font.font = font.clone();
+    }
+}
+
+fn themes_window(
+    mut contexts: EguiContexts,
+    mut themes: ResMut<Themes>,
+    lists: Res<Assets<ThemeList>>,
+    theme_assets: Res<Assets<Theme>>,
+    active_theme: Option<Res<ActiveTheme>>,
+    storage: Res<Storage>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    let Some(list) = lists.get(&themes.list) else {
+        return;
+    };
+    let active = active_theme.map(|active| active.0.name.clone());
+    egui::Window::new("Theme").show(ctx, |ui| {
+        for theme in list.0.iter().filter_map(|handle| theme_assets.get(handle)) {
+            let is_active = active.as_ref() == Some(&theme.name);
+            if ui.radio(is_active, theme.name.as_str()).clicked() && !is_active {
+                themes.selected = Some(theme.name.clone());
+                storage.save(THEME_KEY, &theme.name);
+            }
+        }
+    });
+}
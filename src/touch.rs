@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+
+use crate::{
+    direction::Direction, game_state::AppState, main_menu::NumberOfPlayersSelected,
+    movement::ProposeDirection, snake::Id,
+};
+
+/// How far, in logical pixels, a finger has to move to count as a swipe
+const SWIPE_DISTANCE: f32 = 40.0;
+/// Side of the square each on-screen D-pad takes, in logical pixels
+const D_PAD_SIZE: f32 = 180.0;
+const D_PAD_MARGIN: f32 = 10.0;
+
+pub(crate) struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .init_resource::<SwipeOrigins>()
+            .add_systems(OnEnter(AppState::InGame), spawn_d_pads)
+            .add_systems(
+                Update,
+                (swipe, press_d_pads).run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), (despawn_d_pads, forget_swipes))
+            .add_systems(
+                EguiPrimaryContextPass,
+                touch_controls_selection.run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct TouchControls {
+    /// One D-pad per player in the corners of the screen, instead of swiping for player 1
+    d_pads: bool,
+}
+
+/// Where each finger started its current swipe, by touch id
+#[derive(Resource, Default)]
+struct SwipeOrigins(HashMap<u64, Vec2>);
+
+/// Corner the D-pad of each player goes to, matching where its snake spawns
+#[derive(Clone, Copy)]
+enum Corner {
+    BottomLeft,
+    TopRight,
+    TopLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn of(player_number: u8) -> Option<Corner> {
+        match player_number {
+            1 => Some(Corner::BottomLeft),
+            2 => Some(Corner::TopRight),
+            3 => Some(Corner::TopLeft),
+            4 => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Top left of the D-pad in a window of the given size
+    fn origin(self, window_size: Vec2) -> Vec2 {
+        let far = window_size - Vec2::splat(D_PAD_SIZE + D_PAD_MARGIN);
+        match self {
+            Corner::BottomLeft => Vec2::new(D_PAD_MARGIN, far.y),
+            Corner::TopRight => Vec2::new(far.x, D_PAD_MARGIN),
+            Corner::TopLeft => Vec2::splat(D_PAD_MARGIN),
+            Corner::BottomRight => far,
+        }
+    }
+}
+
+/// Direction of a movement on screen, where y grows downwards
+fn screen_direction(movement: Vec2) -> Direction {
+    if movement.x.abs() > movement.y.abs() {
+        if movement.x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    } else if movement.y > 0.0 {
+        Direction::Down
+    } else {
+        Direction::Up
+    }
+}
+
+/// Arrow of the D-pad under `position`, if any
+fn d_pad_direction(position: Vec2, corner: Corner, window_size: Vec2) -> Option<Direction> {
+    let from_center = position - corner.origin(window_size) - Vec2::splat(D_PAD_SIZE / 2.0);
+    let outside = from_center.abs().max_element() > D_PAD_SIZE / 2.0;
+    // The center of the D-pad is dead space between the arrows
+    let center = from_center.abs().max_element() < D_PAD_SIZE / 6.0;
+    if outside || center {
+        return None;
+    }
+    Some(screen_direction(from_center))
+}
+
+fn swipe(
+    touches: Res<Touches>,
+    touch_controls: Res<TouchControls>,
+    mut swipe_origins: ResMut<SwipeOrigins>,
+    mut propose_direction: MessageWriter<ProposeDirection>,
+) {
+    for touch in touches
+        .iter_just_released()
+        .chain(touches.iter_just_canceled())
+    {
+        swipe_origins.0.remove(&touch.id());
+    }
+    if touch_controls.d_pads {
+        return;
+    }
+
+    for touch in touches.iter() {
+        let origin = swipe_origins
+            .0
+            .entry(touch.id())
+            .or_insert(touch.start_position());
+        let movement = touch.position() - *origin;
+        if movement.length() < SWIPE_DISTANCE {
+            continue;
+        }
+        // Keeping the finger down and changing direction makes another swipe
+        *origin = touch.position();
+        propose_direction.write(ProposeDirection {
+            id: Id(1),
+            direction: screen_direction(movement),
+        });
+    }
+}
+
+fn press_d_pads(
+    touches: Res<Touches>,
+    touch_controls: Res<TouchControls>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut propose_direction: MessageWriter<ProposeDirection>,
+) {
+    if !touch_controls.d_pads {
+        return;
+    }
+    let Ok(window) = windows.single() else {
+        return;
+    };
+
+    for touch in touches.iter_just_pressed() {
+        for player_number in (1..=number_of_players.0).filter_map(|n| u8::try_from(n).ok()) {
+            let Some(corner) = Corner::of(player_number) else {
+                continue;
+            };
+            if let Some(direction) = d_pad_direction(touch.position(), corner, window.size()) {
+                propose_direction.write(ProposeDirection {
+                    id: Id(player_number),
+                    direction,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct DPad;
+
+fn spawn_d_pads(
+    mut commands: Commands,
+    touch_controls: Res<TouchControls>,
+    number_of_players: Res<NumberOfPlayersSelected>,
+) {
+    if !touch_controls.d_pads {
+        return;
+    }
+
+    let button_size = D_PAD_SIZE / 3.0;
+    let arrow = |label: &str, column: f32, row: f32| {
+        (
+            Text::new(label),
+            TextFont {
+                font_size: button_size / 2.0,
+                ..default()
+            },
+            TextLayout::new_with_justify(Justify::Center),
+            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.2)),
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(column * button_size),
+                top: Val::Px(row * button_size),
+                width: Val::Px(button_size),
+                height: Val::Px(button_size),
+                ..default()
+            },
+        )
+    };
+
+    for player_number in (1..=number_of_players.0).filter_map(|n| u8::try_from(n).ok()) {
+        let Some(corner) = Corner::of(player_number) else {
+            continue;
+        };
+        let margin = Val::Px(D_PAD_MARGIN);
+        let (top, bottom, left, right) = match corner {
+            Corner::BottomLeft => (Val::Auto, margin, margin, Val::Auto),
+            Corner::TopRight => (margin, Val::Auto, Val::Auto, margin),
+            Corner::TopLeft => (margin, Val::Auto, margin, Val::Auto),
+            Corner::BottomRight => (Val::Auto, margin, Val::Auto, margin),
+        };
+        let node = Node {
+            position_type: PositionType::Absolute,
+            top,
+            bottom,
+            left,
+            right,
+            width: Val::Px(D_PAD_SIZE),
+            height: Val::Px(D_PAD_SIZE),
+            ..default()
+        };
+
+        commands.spawn((
+            node,
+            DPad,
+            children![
+                arrow("^", 1.0, 0.0),
+                arrow("<", 0.0, 1.0),
+                arrow(">", 2.0, 1.0),
+                arrow("v", 1.0, 2.0),
+            ],
+        ));
+    }
+}
+
+fn despawn_d_pads(mut commands: Commands, d_pads: Query<Entity, With<DPad>>) {
+    for d_pad in d_pads.iter() {
+        commands.entity(d_pad).despawn();
+    }
+}
+
+fn forget_swipes(mut swipe_origins: ResMut<SwipeOrigins>) {
+    swipe_origins.0.clear();
+}
+
+fn touch_controls_selection(mut contexts: EguiContexts, mut touch_controls: ResMut<TouchControls>) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Touch").show(ctx, |ui| {
+        ui.checkbox(&mut touch_controls.d_pads, "On-screen D-pads");
+        ui.label("Without them, swipe to move player 1");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::message::MessageCursor,
+        input::{
+            touch::{TouchInput, TouchPhase},
+            InputPlugin,
+        },
+        window::WindowResolution,
+    };
+
+    use super::*;
+
+    const WINDOW_SIZE: Vec2 = Vec2::new(800.0, 600.0);
+
+    struct TouchApp {
+        app: App,
+        window: Entity,
+        proposed: MessageCursor<ProposeDirection>,
+    }
+
+    impl TouchApp {
+        fn new(d_pads: bool, number_of_players: usize) -> Self {
+            let mut app = App::new();
+            app.add_plugins((MinimalPlugins, InputPlugin))
+                .add_message::<ProposeDirection>()
+                .insert_resource(TouchControls { d_pads })
+                .insert_resource(NumberOfPlayersSelected(number_of_players))
+                .init_resource::<SwipeOrigins>()
+                .add_systems(Update, (swipe, press_d_pads));
+            let window = app
+                .world_mut()
+                .spawn((
+                    Window {
+                        resolution: WindowResolution::new(
+                            WINDOW_SIZE.x as u32,
+                            WINDOW_SIZE.y as u32,
+                        ),
+                        ..default()
+                    },
+                    PrimaryWindow,
+                ))
+                .id();
+            Self {
+                app,
+                window,
+                proposed: MessageCursor::default(),
+            }
+        }
+
+        /// Runs a frame with the finger at `position` and returns what was proposed
+        fn touch(&mut self, phase: TouchPhase, position: Vec2) -> Vec<(u8, Direction)> {
+            let window = self.window;
+            self.app.world_mut().write_message(TouchInput {
+                phase,
+                position,
+                window,
+                force: None,
+                id: 0,
+            });
+            self.app.update();
+            let messages = self.app.world().resource::<Messages<ProposeDirection>>();
+            self.proposed
+                .read(messages)
+                .map(|proposed| (proposed.id.0, proposed.direction))
+                .collect()
+        }
+
+        fn tap(&mut self, position: Vec2) -> Vec<(u8, Direction)> {
+            let proposed = self.touch(TouchPhase::Started, position);
+            self.touch(TouchPhase::Ended, position);
+            proposed
+        }
+    }
+
+    fn d_pad_center(corner: Corner) -> Vec2 {
+        corner.origin(WINDOW_SIZE) + Vec2::splat(D_PAD_SIZE / 2.0)
+    }
+
+    #[test]
+    fn d_pad_arrows_move_the_player_in_their_corner() {
+        let mut touch_app = TouchApp::new(true, 4);
+        let arrow = D_PAD_SIZE / 3.0;
+
+        let bottom_left = d_pad_center(Corner::BottomLeft);
+        assert_eq!(
+            touch_app.tap(bottom_left - Vec2::Y * arrow),
+            vec![(1, Direction::Up)]
+        );
+        assert_eq!(
+            touch_app.tap(bottom_left + Vec2::Y * arrow),
+            vec![(1, Direction::Down)]
+        );
+
+        let top_right = d_pad_center(Corner::TopRight);
+        assert_eq!(
+            touch_app.tap(top_right + Vec2::X * arrow),
+            vec![(2, Direction::Right)]
+        );
+
+        let top_left = d_pad_center(Corner::TopLeft);
+        assert_eq!(
+            touch_app.tap(top_left - Vec2::X * arrow),
+            vec![(3, Direction::Left)]
+        );
+
+        let bottom_right = d_pad_center(Corner::BottomRight);
+        assert_eq!(
+            touch_app.tap(bottom_right - Vec2::Y * arrow),
+            vec![(4, Direction::Up)]
+        );
+    }
+
+    #[test]
+    fn d_pad_center_and_outside_do_nothing() {
+        let mut touch_app = TouchApp::new(true, 4);
+        assert_eq!(touch_app.tap(d_pad_center(Corner::BottomLeft)), vec![]);
+        assert_eq!(touch_app.tap(WINDOW_SIZE / 2.0), vec![]);
+        // Just past the edge of the D-pad
+        let outside = d_pad_center(Corner::TopLeft) + Vec2::X * (D_PAD_SIZE / 2.0 + 1.0);
+        assert_eq!(touch_app.tap(outside), vec![]);
+    }
+
+    #[test]
+    fn d_pads_of_missing_players_do_nothing() {
+        let mut touch_app = TouchApp::new(true, 2);
+        let top_left = d_pad_center(Corner::TopLeft);
+        assert_eq!(touch_app.tap(top_left - Vec2::X * D_PAD_SIZE / 3.0), vec![]);
+    }
+
+    #[test]
+    fn swipes_move_player_one() {
+        let mut touch_app = TouchApp::new(false, 2);
+        let start = WINDOW_SIZE / 2.0;
+        assert_eq!(touch_app.touch(TouchPhase::Started, start), vec![]);
+        // Too short to count
+        let short = start + Vec2::X * (SWIPE_DISTANCE / 2.0);
+        assert_eq!(touch_app.touch(TouchPhase::Moved, short), vec![]);
+        let right = start + Vec2::new(SWIPE_DISTANCE * 1.5, 5.0);
+        assert_eq!(
+            touch_app.touch(TouchPhase::Moved, right),
+            vec![(1, Direction::Right)]
+        );
+        // Turning without lifting the finger is another swipe
+        let down = right + Vec2::Y * SWIPE_DISTANCE * 1.5;
+        assert_eq!(
+            touch_app.touch(TouchPhase::Moved, down),
+            vec![(1, Direction::Down)]
+        );
+        touch_app.touch(TouchPhase::Ended, down);
+    }
+}
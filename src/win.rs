@@ -1,43 +1,52 @@
 use bevy::prelude::*;
 
-use crate::game_state::AppState;
+use crate::game_state::InMatch;
+use crate::rules::GameRules;
+use crate::schedule::{MatchSetupSet, TickSet};
 use crate::snake::{MyColor, Snake};
 
-const LENGTH_TO_WIN: usize = 10;
-const HOLD_TIME_TO_WIN: f32 = 10.0;
-
 pub(crate) struct WinPlugin;
 
 impl Plugin for WinPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(WinnerHoldTimer(Timer::from_seconds(
-            HOLD_TIME_TO_WIN,
+            GameRules::default().hold_time_to_win,
             TimerMode::Repeating,
         )))
         .insert_resource(CurrentFirst(None))
         .add_message::<Won>()
         .add_systems(Startup, setup)
-        .add_systems(
-            Update,
-            (set_first, update_timer_text, win).run_if(in_state(AppState::InGame)),
-        );
+        .add_systems(OnEnter(InMatch), reset_winner.in_set(MatchSetupSet::Spawn))
+        .add_systems(FixedUpdate, (set_first, win).chain().in_set(TickSet::Win))
+        .add_systems(Update, update_timer_text.run_if(in_state(InMatch)));
     }
 }
 
 /// Defines the time that the winner must hold the position to win
 #[derive(Resource)]
-struct WinnerHoldTimer(Timer);
+pub(crate) struct WinnerHoldTimer(pub(crate) Timer);
 
 #[derive(Resource)]
-struct CurrentFirst(Option<(String, Color)>);
+pub(crate) struct CurrentFirst(pub(crate) Option<(String, Color)>);
 
 // TODO: should this event get injected from main into this plugin?
 #[derive(Message)]
 pub(crate) struct Won(pub(crate) String);
 
+fn reset_winner(
+    mut timer: ResMut<WinnerHoldTimer>,
+    mut current_winner: ResMut<CurrentFirst>,
+    rules: Res<GameRules>,
+) {
+    timer.0 = Timer::from_seconds(rules.hold_time_to_win, TimerMode::Repeating);
+    current_winner.0 = None;
+}
+
+// Looks at every snake instead of the changed ones, change detection isn't part of a snapshot
 fn set_first(
-    snakes: Query<(&Snake, &MyColor), Changed<Snake>>,
+    snakes: Query<(&Snake, &MyColor)>,
     mut current_winner: ResMut<CurrentFirst>,
+    rules: Res<GameRules>,
 ) {
     let mut snakes = Vec::from_iter(snakes.iter());
     snakes.sort_by_key(|(snake, _)| snake.segments.len() as i8);
@@ -48,7 +57,7 @@ fn set_first(
     if let (Some((first_snake, first_color)), Some((second_snake, _))) = (first, second) {
         // TODO: remove clones
         if first_snake.segments.len() > second_snake.segments.len()
-            && first_snake.segments.len() >= LENGTH_TO_WIN
+            && first_snake.segments.len() >= rules.length_to_win
         {
             current_winner.0 = Some((first_snake.name.clone(), first_color.0.clone()));
         } else {